@@ -0,0 +1,626 @@
+use retro_filter::{
+    BlendMode, ExportFormat, FilmStock, GrainColor, LutInterpolation, Tint, VignetteCurve,
+    VignetteShape,
+};
+use std::{fmt, path::PathBuf};
+
+pub const USAGE: &str = "\
+Usage: retrofilter-cli [OPTIONS] --output <DIR> <INPUT>...
+       retrofilter-cli [OPTIONS] --export-lut <FILE>
+
+Inputs can be files or glob patterns like \"photos/*.jpg\".
+
+Options:
+  -o, --output <DIR>             directory the processed images are written to
+  -f, --format <FORMAT>          output format [possible values: jpg, png, tif, tif16, webp]
+                                 [default: jpg]
+  -q, --quality <1-100>          jpg quality [default: 75]
+  -p, --preset <FILE>            apply a look saved as toml, effect options override it
+      --film-stock <STOCK,ALPHA>
+                                 render the image on a film stock [possible values: portra,
+                                 tri-x, velvia, ektachrome]
+      --film-stock-blend <MODE>  blend mode of the film stock [default: normal]
+      --lut <FILE>               map the colors through a .cube file or a haldclut image
+      --lut-alpha <0-1>          alpha of the lut [default: 1]
+      --lut-interpolation <INTERPOLATION>
+                                 [possible values: trilinear, tetrahedral]
+                                 [default: tetrahedral]
+      --lut-blend <MODE>         blend mode of the lut [default: normal]
+      --tone-curve <[CHANNEL:]X,Y;X,Y...>
+                                 map the tones through a curve through the given points,
+                                 all 0-1, can be repeated for the channels [possible
+                                 values: master, red, green, blue] [default: master]
+      --tone-curve-alpha <0-1>   alpha of the tone curve [default: 1]
+      --tone-curve-blend <MODE>  blend mode of the tone curve [default: normal]
+      --grade-shadows <HUE,SATURATION>
+                                 tint the shadows, hue in degrees and saturation 0-1
+      --grade-midtones <HUE,SATURATION>
+                                 tint the midtones
+      --grade-highlights <HUE,SATURATION>
+                                 tint the highlights
+      --grade-balance <-1-1>     move the split between shadows and highlights, positive
+                                 values give more room to the highlights [default: 0]
+      --grade-alpha <0-1>        alpha of the color grade [default: 1]
+      --grade-blend <MODE>       blend mode of the color grade [default: normal]
+      --export-lut <FILE>        write the color changes of the look as a .cube lut, the
+                                 vignette, grain, blur and halation are left out
+      --vignette <RADIUS,ALPHA>  add a vignette, radius relative to the image diagonal
+      --vignette-shape <SHAPE>   shape of the vignette [possible values: circle, ellipse,
+                                 rounded-rect[,ROUNDNESS 0-1]] [default: circle]
+      --vignette-center <X,Y>    focal point of the vignette relative to the image size
+                                 [default: 0.5,0.5]
+      --vignette-curve <CURVE>   falloff of the vignette [possible values: linear, smoothstep,
+                                 cos4] [default: linear]
+      --vignette-blend <MODE>    blend mode of the vignette [default: multiply]
+      --filmgrain <INTENSITY,ALPHA>
+                                 add film grain, intensity 0-1
+      --grain-size <SIZE>        size of a grain relative to the image height [default: 0.001]
+      --grain-roughness <0-1>    0 gives soft and 1 crisp grain [default: 0.5]
+      --grain-chroma <SATURATION[,R,G,B]>
+                                 color grain with its saturation and the amplitude of the
+                                 red, green and blue grain, all 0-1 [default: 0.5,0.8,0.9,1]
+      --grain-blend <MODE>       blend mode of the grain [default: normal]
+      --bleachbypass <BLUR,ALPHA>
+                                 add a bleach bypass effect, blur relative to the image
+                                 diagonal
+      --bleachbypass-contrast <0-1>
+                                 strength of the s-curve [default: 0.3]
+      --bleachbypass-silver <0-1>
+                                 how much of the color the silver replaces [default: 1]
+      --bleachbypass-rolloff <SHADOWS,HIGHLIGHTS>
+                                 compress the ends of the tonal range, both 0-1 and
+                                 together at most 1 [default: 0,0]
+      --bleachbypass-blend <MODE>
+                                 blend mode of the bleach bypass [default: hard-light]
+      --affect-transparent       apply the effects to transparent areas too
+      --strip-gps                remove the gps location from the copied metadata
+      --seed <N>                 seed for the randomness of the effects [default: random]
+  -h, --help                     print this help
+
+Blend modes: normal, multiply, screen, overlay, soft-light, hard-light, color-dodge,
+color-burn, darken, lighten, difference, luminosity, color";
+
+// the quality is set once all options are parsed
+fn parse_format(value: &str) -> Option<ExportFormat> {
+    match value.to_lowercase().as_str() {
+        "tif16" | "tiff16" => Some(ExportFormat::Tiff16),
+        format => ExportFormat::from_extension(format, 0),
+    }
+}
+
+// values like "ellipse" or "rounded-rect,0.5"
+fn parse_shape(value: &str) -> Option<VignetteShape> {
+    let (shape, roundness) = match value.split_once(',') {
+        Some((shape, roundness)) => (shape, Some(roundness.trim().parse().ok()?)),
+        None => (value, None),
+    };
+    match (shape.trim().to_lowercase().as_str(), roundness) {
+        ("circle", None) => Some(VignetteShape::Circle),
+        ("ellipse", None) => Some(VignetteShape::Ellipse),
+        ("rounded-rect", roundness) => {
+            let roundness = roundness.unwrap_or(0.5);
+            (0.0..=1.0)
+                .contains(&roundness)
+                .then_some(VignetteShape::RoundedRect { roundness })
+        }
+        _ => None,
+    }
+}
+
+// values like "0.5" or "0.5,0.8,0.9,1" for the saturation and the amplitudes
+fn parse_chroma(value: &str) -> Option<GrainColor> {
+    let values = value
+        .split(',')
+        .map(|value| value.trim().parse().ok())
+        .collect::<Option<Vec<f32>>>()?;
+    if !values.iter().all(|value| (0.0..=1.0).contains(value)) {
+        return None;
+    }
+    match values[..] {
+        [saturation] => Some(GrainColor::Chroma {
+            amplitude: GrainColor::DEFAULT_AMPLITUDE,
+            saturation,
+        }),
+        [saturation, red, green, blue] => Some(GrainColor::Chroma {
+            amplitude: [red, green, blue],
+            saturation,
+        }),
+        _ => None,
+    }
+}
+
+// values like "portra,0.8" for the stock and its alpha
+fn parse_film_stock(value: &str) -> Option<(FilmStock, f32)> {
+    let (name, alpha) = value.split_once(',')?;
+    let name = name.trim().to_lowercase();
+    let stock = FilmStock::ALL
+        .into_iter()
+        .find(|stock| stock.name().to_lowercase() == name)?;
+    let alpha = alpha.trim().parse().ok()?;
+    (0.0..=1.0).contains(&alpha).then_some((stock, alpha))
+}
+
+// names like "soft-light", the names of the gui with dashes
+fn parse_blend_mode(value: &str) -> Option<BlendMode> {
+    let name = value.trim().to_lowercase().replace('_', "-");
+    BlendMode::ALL
+        .into_iter()
+        .find(|mode| mode.name().to_lowercase().replace(' ', "-") == name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurveChannel {
+    Master,
+    Red,
+    Green,
+    Blue,
+}
+
+// values like "0,0.1;0.5,0.5;1,0.9" or "blue:0,0.05;1,1"
+fn parse_curve(value: &str) -> Option<(CurveChannel, Vec<(f32, f32)>)> {
+    let (channel, points) = match value.split_once(':') {
+        Some((channel, points)) => {
+            let channel = match channel.trim().to_lowercase().as_str() {
+                "master" => CurveChannel::Master,
+                "red" => CurveChannel::Red,
+                "green" => CurveChannel::Green,
+                "blue" => CurveChannel::Blue,
+                _ => return None,
+            };
+            (channel, points)
+        }
+        None => (CurveChannel::Master, value),
+    };
+    let points = points
+        .split(';')
+        .map(|point| {
+            let (x, y) = point.split_once(',')?;
+            let (x, y): (f32, f32) = (x.trim().parse().ok()?, y.trim().parse().ok()?);
+            ((0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y)).then_some((x, y))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (points.len() >= 2).then_some((channel, points))
+}
+
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub inputs: Vec<String>,
+    pub output: PathBuf,
+    pub format: ExportFormat,
+    pub preset: Option<PathBuf>,
+    pub film_stock: Option<(FilmStock, f32)>,
+    pub film_stock_blend: Option<BlendMode>,
+    pub lut: Option<PathBuf>,
+    pub lut_alpha: Option<f32>,
+    pub lut_interpolation: Option<LutInterpolation>,
+    pub lut_blend: Option<BlendMode>,
+    pub tone_curves: Vec<(CurveChannel, Vec<(f32, f32)>)>,
+    pub tone_curve_alpha: Option<f32>,
+    pub tone_curve_blend: Option<BlendMode>,
+    pub grade_shadows: Option<Tint>,
+    pub grade_midtones: Option<Tint>,
+    pub grade_highlights: Option<Tint>,
+    pub grade_balance: Option<f32>,
+    pub grade_alpha: Option<f32>,
+    pub grade_blend: Option<BlendMode>,
+    pub export_lut: Option<PathBuf>,
+    pub vignette: Option<(f64, f64)>,
+    pub vignette_shape: Option<VignetteShape>,
+    pub vignette_center: Option<(f32, f32)>,
+    pub vignette_curve: Option<VignetteCurve>,
+    pub vignette_blend: Option<BlendMode>,
+    pub filmgrain: Option<(f64, f64)>,
+    pub grain_size: Option<f32>,
+    pub grain_roughness: Option<f32>,
+    pub grain_chroma: Option<GrainColor>,
+    pub grain_blend: Option<BlendMode>,
+    pub bleachbypass: Option<(f64, f64)>,
+    pub bleachbypass_contrast: Option<f32>,
+    pub bleachbypass_silver: Option<f32>,
+    pub bleachbypass_rolloff: Option<(f32, f32)>,
+    pub bleachbypass_blend: Option<BlendMode>,
+    pub affect_transparent: bool,
+    pub strip_gps: bool,
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum ArgsError {
+    Help,
+    MissingValue(String),
+    InvalidValue(String, String),
+    Unknown(String),
+    NoInput,
+    NoOutput,
+}
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgsError::Help => write!(f, "{}", USAGE),
+            ArgsError::MissingValue(flag) => write!(f, "missing value for '{}'", flag),
+            ArgsError::InvalidValue(flag, value) => {
+                write!(f, "invalid value '{}' for '{}'", value, flag)
+            }
+            ArgsError::Unknown(flag) => write!(f, "unknown option '{}'", flag),
+            ArgsError::NoInput => write!(f, "no input files given"),
+            ArgsError::NoOutput => write!(f, "no output directory given"),
+        }
+    }
+}
+
+impl Args {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, ArgsError> {
+        let mut inputs = Vec::new();
+        let mut output = None;
+        let mut format = ExportFormat::default();
+        let mut quality = 75;
+        let mut preset = None;
+        let mut film_stock = None;
+        let mut film_stock_blend = None;
+        let mut lut = None;
+        let mut lut_alpha = None;
+        let mut lut_interpolation = None;
+        let mut lut_blend = None;
+        let mut tone_curves = Vec::new();
+        let mut tone_curve_alpha = None;
+        let mut tone_curve_blend = None;
+        let mut grade_shadows = None;
+        let mut grade_midtones = None;
+        let mut grade_highlights = None;
+        let mut grade_balance = None;
+        let mut grade_alpha = None;
+        let mut grade_blend = None;
+        let mut export_lut = None;
+        let mut vignette = None;
+        let mut vignette_shape = None;
+        let mut vignette_center = None;
+        let mut vignette_curve = None;
+        let mut vignette_blend = None;
+        let mut filmgrain = None;
+        let mut grain_size = None;
+        let mut grain_roughness = None;
+        let mut grain_chroma = None;
+        let mut grain_blend = None;
+        let mut bleachbypass = None;
+        let mut bleachbypass_contrast = None;
+        let mut bleachbypass_silver = None;
+        let mut bleachbypass_rolloff = None;
+        let mut bleachbypass_blend = None;
+        let mut affect_transparent = false;
+        let mut strip_gps = false;
+        let mut seed = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Err(ArgsError::Help),
+                "-o" | "--output" => output = Some(PathBuf::from(value(&arg, &mut args)?)),
+                "-f" | "--format" => {
+                    let value = value(&arg, &mut args)?;
+                    format = parse_format(&value).ok_or(ArgsError::InvalidValue(arg, value))?;
+                }
+                "-q" | "--quality" => {
+                    quality = parse_number(&arg, &mut args)?;
+                    if !(1..=100).contains(&quality) {
+                        return Err(ArgsError::InvalidValue(arg, quality.to_string()));
+                    }
+                }
+                "-p" | "--preset" => preset = Some(PathBuf::from(value(&arg, &mut args)?)),
+                "--film-stock" => {
+                    let value = value(&arg, &mut args)?;
+                    film_stock =
+                        Some(parse_film_stock(&value).ok_or(ArgsError::InvalidValue(arg, value))?);
+                }
+                "--film-stock-blend" => film_stock_blend = Some(blend_mode(&arg, &mut args)?),
+                "--lut" => lut = Some(PathBuf::from(value(&arg, &mut args)?)),
+                "--lut-alpha" => {
+                    let alpha: f32 = parse_number(&arg, &mut args)?;
+                    if !(0.0..=1.0).contains(&alpha) {
+                        return Err(ArgsError::InvalidValue(arg, alpha.to_string()));
+                    }
+                    lut_alpha = Some(alpha);
+                }
+                "--lut-interpolation" => {
+                    let value = value(&arg, &mut args)?;
+                    lut_interpolation = Some(match value.to_lowercase().as_str() {
+                        "trilinear" => LutInterpolation::Trilinear,
+                        "tetrahedral" => LutInterpolation::Tetrahedral,
+                        _ => return Err(ArgsError::InvalidValue(arg, value)),
+                    });
+                }
+                "--lut-blend" => lut_blend = Some(blend_mode(&arg, &mut args)?),
+                "--tone-curve" => {
+                    let value = value(&arg, &mut args)?;
+                    tone_curves
+                        .push(parse_curve(&value).ok_or(ArgsError::InvalidValue(arg, value))?);
+                }
+                "--tone-curve-alpha" => tone_curve_alpha = Some(parse_fraction(&arg, &mut args)?),
+                "--tone-curve-blend" => tone_curve_blend = Some(blend_mode(&arg, &mut args)?),
+                "--grade-shadows" => grade_shadows = Some(parse_tint(&arg, &mut args)?),
+                "--grade-midtones" => grade_midtones = Some(parse_tint(&arg, &mut args)?),
+                "--grade-highlights" => grade_highlights = Some(parse_tint(&arg, &mut args)?),
+                "--grade-balance" => {
+                    let balance: f32 = parse_number(&arg, &mut args)?;
+                    if !(-1.0..=1.0).contains(&balance) {
+                        return Err(ArgsError::InvalidValue(arg, balance.to_string()));
+                    }
+                    grade_balance = Some(balance);
+                }
+                "--grade-alpha" => grade_alpha = Some(parse_fraction(&arg, &mut args)?),
+                "--grade-blend" => grade_blend = Some(blend_mode(&arg, &mut args)?),
+                "--export-lut" => export_lut = Some(PathBuf::from(value(&arg, &mut args)?)),
+                "--vignette" => vignette = Some(parse_pair(&arg, &mut args)?),
+                "--vignette-shape" => {
+                    let value = value(&arg, &mut args)?;
+                    vignette_shape =
+                        Some(parse_shape(&value).ok_or(ArgsError::InvalidValue(arg, value))?);
+                }
+                "--vignette-center" => {
+                    let (x, y) = parse_pair(&arg, &mut args)?;
+                    if !(0.0..=1.0).contains(&x) {
+                        return Err(ArgsError::InvalidValue(arg, format!("{},{}", x, y)));
+                    }
+                    vignette_center = Some((x as f32, y as f32));
+                }
+                "--vignette-curve" => {
+                    let value = value(&arg, &mut args)?;
+                    vignette_curve = Some(match value.to_lowercase().as_str() {
+                        "linear" => VignetteCurve::Linear,
+                        "smoothstep" => VignetteCurve::Smoothstep,
+                        "cos4" => VignetteCurve::Cos4,
+                        _ => return Err(ArgsError::InvalidValue(arg, value)),
+                    });
+                }
+                "--vignette-blend" => vignette_blend = Some(blend_mode(&arg, &mut args)?),
+                "--filmgrain" => filmgrain = Some(parse_pair(&arg, &mut args)?),
+                "--grain-size" => grain_size = Some(parse_number(&arg, &mut args)?),
+                "--grain-roughness" => grain_roughness = Some(parse_number(&arg, &mut args)?),
+                "--grain-chroma" => {
+                    let value = value(&arg, &mut args)?;
+                    grain_chroma =
+                        Some(parse_chroma(&value).ok_or(ArgsError::InvalidValue(arg, value))?);
+                }
+                "--grain-blend" => grain_blend = Some(blend_mode(&arg, &mut args)?),
+                "--bleachbypass" => bleachbypass = Some(parse_pair(&arg, &mut args)?),
+                "--bleachbypass-contrast" => {
+                    bleachbypass_contrast = Some(parse_fraction(&arg, &mut args)?)
+                }
+                "--bleachbypass-silver" => {
+                    bleachbypass_silver = Some(parse_fraction(&arg, &mut args)?)
+                }
+                "--bleachbypass-rolloff" => {
+                    let (shadows, highlights) = parse_pair(&arg, &mut args)?;
+                    if !(0.0..=1.0).contains(&shadows) || shadows + highlights > 1.0 {
+                        return Err(ArgsError::InvalidValue(
+                            arg,
+                            format!("{},{}", shadows, highlights),
+                        ));
+                    }
+                    bleachbypass_rolloff = Some((shadows as f32, highlights as f32));
+                }
+                "--bleachbypass-blend" => bleachbypass_blend = Some(blend_mode(&arg, &mut args)?),
+                "--affect-transparent" => affect_transparent = true,
+                "--strip-gps" => strip_gps = true,
+                "--seed" => seed = Some(parse_number(&arg, &mut args)?),
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(ArgsError::Unknown(arg));
+                }
+                _ => inputs.push(arg),
+            }
+        }
+
+        // only exporting a lut needs neither inputs nor an output directory
+        let output = match output {
+            Some(output) => output,
+            None if inputs.is_empty() && export_lut.is_some() => PathBuf::new(),
+            None => return Err(ArgsError::NoOutput),
+        };
+        if inputs.is_empty() && export_lut.is_none() {
+            return Err(ArgsError::NoInput);
+        }
+        Ok(Self {
+            inputs,
+            output,
+            format: match format {
+                ExportFormat::Jpeg { .. } => ExportFormat::Jpeg { quality },
+                format => format,
+            },
+            preset,
+            film_stock,
+            film_stock_blend,
+            lut,
+            lut_alpha,
+            lut_interpolation,
+            lut_blend,
+            tone_curves,
+            tone_curve_alpha,
+            tone_curve_blend,
+            grade_shadows,
+            grade_midtones,
+            grade_highlights,
+            grade_balance,
+            grade_alpha,
+            grade_blend,
+            export_lut,
+            vignette,
+            vignette_shape,
+            vignette_center,
+            vignette_curve,
+            vignette_blend,
+            filmgrain,
+            grain_size,
+            grain_roughness,
+            grain_chroma,
+            grain_blend,
+            bleachbypass,
+            bleachbypass_contrast,
+            bleachbypass_silver,
+            bleachbypass_rolloff,
+            bleachbypass_blend,
+            affect_transparent,
+            strip_gps,
+            seed,
+        })
+    }
+}
+
+fn value<I: Iterator<Item = String>>(flag: &str, args: &mut I) -> Result<String, ArgsError> {
+    args.next()
+        .ok_or_else(|| ArgsError::MissingValue(flag.to_string()))
+}
+
+fn parse_number<T: std::str::FromStr, I: Iterator<Item = String>>(
+    flag: &str,
+    args: &mut I,
+) -> Result<T, ArgsError> {
+    let value = value(flag, args)?;
+    value
+        .parse()
+        .map_err(|_| ArgsError::InvalidValue(flag.to_string(), value))
+}
+
+// a number between 0 and 1
+fn parse_fraction<I: Iterator<Item = String>>(flag: &str, args: &mut I) -> Result<f32, ArgsError> {
+    let value: f32 = parse_number(flag, args)?;
+    if !(0.0..=1.0).contains(&value) {
+        return Err(ArgsError::InvalidValue(flag.to_string(), value.to_string()));
+    }
+    Ok(value)
+}
+
+fn blend_mode<I: Iterator<Item = String>>(
+    flag: &str,
+    args: &mut I,
+) -> Result<BlendMode, ArgsError> {
+    let value = value(flag, args)?;
+    parse_blend_mode(&value).ok_or_else(|| ArgsError::InvalidValue(flag.to_string(), value))
+}
+
+// values like "190,0.3" for the hue in degrees and the saturation
+fn parse_tint<I: Iterator<Item = String>>(flag: &str, args: &mut I) -> Result<Tint, ArgsError> {
+    let (hue, saturation) = parse_pair(flag, args)?;
+    Ok(Tint {
+        hue: hue as f32,
+        saturation: saturation as f32,
+    })
+}
+
+// values like "250,0.7" for the effect parameter and its alpha
+fn parse_pair<I: Iterator<Item = String>>(
+    flag: &str,
+    args: &mut I,
+) -> Result<(f64, f64), ArgsError> {
+    let value = value(flag, args)?;
+    let invalid = || ArgsError::InvalidValue(flag.to_string(), value.clone());
+    let (parameter, alpha) = value.split_once(',').ok_or_else(invalid)?;
+    let parameter: f64 = parameter.trim().parse().map_err(|_| invalid())?;
+    let alpha: f64 = alpha.trim().parse().map_err(|_| invalid())?;
+    if !(0.0..=1.0).contains(&alpha) {
+        return Err(invalid());
+    }
+    Ok((parameter, alpha))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, ArgsError> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_effects() {
+        let args = parse(&[
+            "-o",
+            "out",
+            "--vignette",
+            "0.5,0.7",
+            "--grade-shadows",
+            "190,0.3",
+            "--bleachbypass-blend",
+            "soft-light",
+            "--tone-curve",
+            "blue:0,0.05;1,1",
+            "-f",
+            "png",
+            "--seed",
+            "7",
+            "a.jpg",
+            "b.jpg",
+        ])
+        .unwrap();
+        assert_eq!(args.inputs, ["a.jpg", "b.jpg"]);
+        assert_eq!(args.output, PathBuf::from("out"));
+        assert_eq!(args.vignette, Some((0.5, 0.7)));
+        assert_eq!(
+            args.grade_shadows,
+            Some(Tint {
+                hue: 190.0,
+                saturation: 0.3
+            })
+        );
+        assert_eq!(args.bleachbypass_blend, Some(BlendMode::SoftLight));
+        assert_eq!(
+            args.tone_curves,
+            [(CurveChannel::Blue, vec![(0.0, 0.05), (1.0, 1.0)])]
+        );
+        assert_eq!(args.format.extension(), "png");
+        assert_eq!(args.seed, Some(7));
+    }
+
+    #[test]
+    fn sets_the_jpg_quality() {
+        let args = parse(&["-q", "90", "-o", "out", "a.png"]).unwrap();
+        assert!(matches!(args.format, ExportFormat::Jpeg { quality: 90 }));
+    }
+
+    #[test]
+    fn exports_a_lut_without_inputs() {
+        let args = parse(&["--export-lut", "look.cube"]).unwrap();
+        assert!(args.inputs.is_empty());
+        assert_eq!(args.export_lut, Some(PathBuf::from("look.cube")));
+    }
+
+    #[test]
+    fn reports_invalid_arguments() {
+        assert!(matches!(parse(&["--help", "-o"]), Err(ArgsError::Help)));
+        assert!(matches!(parse(&["a.jpg"]), Err(ArgsError::NoOutput)));
+        assert!(matches!(parse(&["-o", "out"]), Err(ArgsError::NoInput)));
+        assert!(matches!(
+            parse(&["a.jpg", "-o"]),
+            Err(ArgsError::MissingValue(flag)) if flag == "-o"
+        ));
+        assert!(matches!(
+            parse(&["--vinyette", "0.5,0.7"]),
+            Err(ArgsError::Unknown(flag)) if flag == "--vinyette"
+        ));
+        for (flag, value) in [
+            ("--quality", "0"),
+            ("--format", "bmp"),
+            ("--vignette", "0.5"),
+            ("--filmgrain", "0.5,1.5"),
+            ("--grade-balance", "2"),
+            ("--grade-blend", "normalish"),
+            ("--tone-curve", "0.5,0.5"),
+            ("--vignette-shape", "rounded-rect,2"),
+            ("--grain-chroma", "0.5,1,1"),
+            ("--film-stock", "kodachrome,1"),
+            ("--seed", "-1"),
+            ("--bleachbypass-rolloff", "0.6,0.5"),
+        ] {
+            match parse(&[flag, value, "-o", "out", "a.jpg"]) {
+                Err(ArgsError::InvalidValue(invalid_flag, _)) => assert_eq!(invalid_flag, flag),
+                result => panic!("{} {} gave {:?}", flag, value, result),
+            }
+        }
+    }
+
+    #[test]
+    fn names_the_invalid_value() {
+        let err = parse(&["--lut-interpolation", "cubic"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid value 'cubic' for '--lut-interpolation'"
+        );
+    }
+}
@@ -0,0 +1,386 @@
+mod args;
+use args::{Args, ArgsError, CurveChannel, USAGE};
+use rayon::prelude::*;
+use retro_filter::{
+    image_open_with_metadata, image_save_with_metadata, BleachBypassSettings, BlendMode,
+    ColorGradeSettings, Context, FilmStockSettings, GrainColor, GrainSettings, LutInterpolation,
+    LutSettings, Pipeline, Preset, Tint, ToneCurve, ToneCurveSettings, VignetteCurve,
+    VignetteSettings, VignetteShape,
+};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+// entries per axis of an exported lut
+const EXPORT_LUT_SIZE: usize = 33;
+
+// exit codes
+const EXIT_FAILURE: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(ArgsError::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    let inputs = match expand_inputs(&args.inputs) {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    if let Err(err) = check_output_names(&inputs, &args) {
+        eprintln!("error: {}", err);
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if !inputs.is_empty() {
+        if let Err(err) = fs::create_dir_all(&args.output) {
+            eprintln!(
+                "error: could not create output directory '{}': {}",
+                args.output.display(),
+                err
+            );
+            return ExitCode::from(EXIT_FAILURE);
+        }
+    }
+
+    let preset = match preset(&args) {
+        Ok(preset) => preset,
+        Err(err) => {
+            eprintln!("error: could not load preset: {}", err);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    let pipeline = match preset.pipeline() {
+        Ok(pipeline) => pipeline,
+        Err(err) => {
+            eprintln!("error: could not load lut: {}", err);
+            return ExitCode::from(EXIT_FAILURE);
+        }
+    };
+    if let Some(path) = &args.export_lut {
+        let exported = pipeline
+            .to_lut(EXPORT_LUT_SIZE)
+            .and_then(|lut| lut.save(path, &preset.name));
+        if let Err(err) = exported {
+            eprintln!("error: could not export lut '{}': {}", path.display(), err);
+            return ExitCode::from(EXIT_FAILURE);
+        }
+        println!("look -> {}", path.display());
+        if inputs.is_empty() {
+            return ExitCode::SUCCESS;
+        }
+    }
+    let seed = match args.seed.or(preset.seed) {
+        Some(seed) => seed,
+        None => {
+            let seed = rand::random();
+            eprintln!("using seed {}", seed);
+            seed
+        }
+    };
+    let failed = inputs
+        .par_iter()
+        .filter(|input| match process_file(input, &pipeline, seed, &args) {
+            Ok(output) => {
+                println!("{} -> {}", input.display(), output.display());
+                false
+            }
+            Err(err) => {
+                eprintln!("error: {}: {}", input.display(), err);
+                true
+            }
+        })
+        .count();
+
+    if failed > 0 {
+        eprintln!("{} of {} images failed", failed, inputs.len());
+        return ExitCode::from(EXIT_FAILURE);
+    }
+    ExitCode::SUCCESS
+}
+
+fn preset(args: &Args) -> Result<Preset, retro_filter::Error> {
+    let mut preset = match &args.preset {
+        Some(path) => Preset::load(path)?,
+        None => Preset::default(),
+    };
+    if args.affect_transparent {
+        preset.affect_transparent = true;
+    }
+    if let Some((stock, opacity)) = args.film_stock {
+        preset.film_stock = Some(FilmStockSettings {
+            stock,
+            opacity,
+            blend_mode: BlendMode::default(),
+        });
+    }
+    if let (Some(blend_mode), Some(film_stock)) =
+        (args.film_stock_blend, preset.film_stock.as_mut())
+    {
+        film_stock.blend_mode = blend_mode;
+    }
+    // the path is relative to the working directory and not to the preset
+    if let Some(path) = &args.lut {
+        preset.lut = Some(LutSettings {
+            path: std::path::absolute(path)?,
+            opacity: 1.0,
+            interpolation: LutInterpolation::default(),
+            blend_mode: BlendMode::default(),
+        });
+    }
+    if let (Some(alpha), Some(lut)) = (args.lut_alpha, preset.lut.as_mut()) {
+        lut.opacity = alpha;
+    }
+    if let (Some(interpolation), Some(lut)) = (args.lut_interpolation, preset.lut.as_mut()) {
+        lut.interpolation = interpolation;
+    }
+    if let (Some(blend_mode), Some(lut)) = (args.lut_blend, preset.lut.as_mut()) {
+        lut.blend_mode = blend_mode;
+    }
+    // curves from the arguments replace the ones of the preset channel by channel
+    for (channel, points) in &args.tone_curves {
+        let tone_curve = preset.tone_curve.get_or_insert_with(|| ToneCurveSettings {
+            opacity: 1.0,
+            master: ToneCurve::IDENTITY.to_vec(),
+            red: ToneCurve::IDENTITY.to_vec(),
+            green: ToneCurve::IDENTITY.to_vec(),
+            blue: ToneCurve::IDENTITY.to_vec(),
+            blend_mode: BlendMode::default(),
+        });
+        let curve = match channel {
+            CurveChannel::Master => &mut tone_curve.master,
+            CurveChannel::Red => &mut tone_curve.red,
+            CurveChannel::Green => &mut tone_curve.green,
+            CurveChannel::Blue => &mut tone_curve.blue,
+        };
+        *curve = points.clone();
+    }
+    if let (Some(alpha), Some(tone_curve)) = (args.tone_curve_alpha, preset.tone_curve.as_mut()) {
+        tone_curve.opacity = alpha;
+    }
+    if let (Some(blend_mode), Some(tone_curve)) =
+        (args.tone_curve_blend, preset.tone_curve.as_mut())
+    {
+        tone_curve.blend_mode = blend_mode;
+    }
+    // a tint creates the color grade, the other tints of the preset are kept
+    let tints = [
+        args.grade_shadows,
+        args.grade_midtones,
+        args.grade_highlights,
+    ];
+    if tints.iter().any(Option::is_some) {
+        let color_grade = preset
+            .color_grade
+            .get_or_insert_with(|| ColorGradeSettings {
+                opacity: 1.0,
+                balance: 0.0,
+                blend_mode: BlendMode::default(),
+                shadows: Tint::default(),
+                midtones: Tint::default(),
+                highlights: Tint::default(),
+            });
+        let [shadows, midtones, highlights] = tints;
+        color_grade.shadows = shadows.unwrap_or(color_grade.shadows);
+        color_grade.midtones = midtones.unwrap_or(color_grade.midtones);
+        color_grade.highlights = highlights.unwrap_or(color_grade.highlights);
+    }
+    if let (Some(balance), Some(color_grade)) = (args.grade_balance, preset.color_grade.as_mut()) {
+        color_grade.balance = balance;
+    }
+    if let (Some(alpha), Some(color_grade)) = (args.grade_alpha, preset.color_grade.as_mut()) {
+        color_grade.opacity = alpha;
+    }
+    if let (Some(blend_mode), Some(color_grade)) = (args.grade_blend, preset.color_grade.as_mut()) {
+        color_grade.blend_mode = blend_mode;
+    }
+    if let Some((blur, opacity)) = args.bleachbypass {
+        preset.bleach_bypass = Some(BleachBypassSettings {
+            blur: blur as f32,
+            opacity: opacity as f32,
+            contrast: BleachBypassSettings::DEFAULT_CONTRAST,
+            silver_retention: BleachBypassSettings::DEFAULT_SILVER_RETENTION,
+            shadow_rolloff: 0.0,
+            highlight_rolloff: 0.0,
+            blend_mode: BleachBypassSettings::DEFAULT_BLEND_MODE,
+        });
+    }
+    if let (Some(contrast), Some(bleach_bypass)) =
+        (args.bleachbypass_contrast, preset.bleach_bypass.as_mut())
+    {
+        bleach_bypass.contrast = contrast;
+    }
+    if let (Some(silver_retention), Some(bleach_bypass)) =
+        (args.bleachbypass_silver, preset.bleach_bypass.as_mut())
+    {
+        bleach_bypass.silver_retention = silver_retention;
+    }
+    if let (Some((shadows, highlights)), Some(bleach_bypass)) =
+        (args.bleachbypass_rolloff, preset.bleach_bypass.as_mut())
+    {
+        bleach_bypass.shadow_rolloff = shadows;
+        bleach_bypass.highlight_rolloff = highlights;
+    }
+    if let (Some(blend_mode), Some(bleach_bypass)) =
+        (args.bleachbypass_blend, preset.bleach_bypass.as_mut())
+    {
+        bleach_bypass.blend_mode = blend_mode;
+    }
+    if let Some((radius, opacity)) = args.vignette {
+        preset.vignette = Some(VignetteSettings {
+            radius,
+            opacity: opacity as f32,
+            center: (0.5, 0.5),
+            curve: VignetteCurve::default(),
+            noise: true,
+            blend_mode: VignetteSettings::DEFAULT_BLEND_MODE,
+            shape: VignetteShape::default(),
+        });
+    }
+    if let (Some(shape), Some(vignette)) = (args.vignette_shape, preset.vignette.as_mut()) {
+        vignette.shape = shape;
+    }
+    if let (Some(center), Some(vignette)) = (args.vignette_center, preset.vignette.as_mut()) {
+        vignette.center = center;
+    }
+    if let (Some(curve), Some(vignette)) = (args.vignette_curve, preset.vignette.as_mut()) {
+        vignette.curve = curve;
+    }
+    if let (Some(blend_mode), Some(vignette)) = (args.vignette_blend, preset.vignette.as_mut()) {
+        vignette.blend_mode = blend_mode;
+    }
+    if let Some((intensity, opacity)) = args.filmgrain {
+        preset.grain = Some(GrainSettings {
+            intensity: intensity as f32,
+            opacity: opacity as f32,
+            size: GrainSettings::DEFAULT_SIZE,
+            roughness: GrainSettings::DEFAULT_ROUGHNESS,
+            blend_mode: BlendMode::default(),
+            color: GrainColor::Monochrome,
+        });
+    }
+    if let (Some(size), Some(grain)) = (args.grain_size, preset.grain.as_mut()) {
+        grain.size = size;
+    }
+    if let (Some(roughness), Some(grain)) = (args.grain_roughness, preset.grain.as_mut()) {
+        grain.roughness = roughness;
+    }
+    if let (Some(color), Some(grain)) = (args.grain_chroma, preset.grain.as_mut()) {
+        grain.color = color;
+    }
+    if let (Some(blend_mode), Some(grain)) = (args.grain_blend, preset.grain.as_mut()) {
+        grain.blend_mode = blend_mode;
+    }
+    Ok(preset)
+}
+
+fn process_file(
+    input: &Path,
+    pipeline: &Pipeline,
+    seed: u64,
+    args: &Args,
+) -> Result<PathBuf, retro_filter::Error> {
+    let (image, mut metadata) = image_open_with_metadata(input.to_path_buf())?;
+    if args.strip_gps {
+        metadata.strip_gps();
+    }
+    let context = Context::new(seed);
+    let image = pipeline.process(&image, &context)?;
+    image_save_with_metadata(&image, &metadata, args.format, output_path(input, args))
+}
+
+// the name is built as a string, with_extension would cut a stem with dots at its last dot
+fn output_path(input: &Path, args: &Args) -> PathBuf {
+    let stem = input.file_stem().unwrap_or(input.as_os_str());
+    args.output.join(format!(
+        "{}.{}",
+        stem.to_string_lossy(),
+        args.format.extension()
+    ))
+}
+
+// inputs are either existing files or glob patterns
+fn expand_inputs(inputs: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = PathBuf::from(input);
+        if path.is_file() {
+            files.push(path);
+            continue;
+        }
+        let entries =
+            glob::glob(input).map_err(|err| format!("invalid pattern '{}': {}", input, err))?;
+        let matches: Vec<PathBuf> = entries
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file())
+            .collect();
+        if matches.is_empty() {
+            return Err(format!("no files found for '{}'", input));
+        }
+        files.extend(matches);
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+// files with the same name from different directories would overwrite each other
+fn check_output_names(inputs: &[PathBuf], args: &Args) -> Result<(), String> {
+    let mut outputs = HashSet::new();
+    for input in inputs {
+        let output = output_path(input, args);
+        if !outputs.insert(output.clone()) {
+            return Err(format!(
+                "more than one input would be written to '{}'",
+                output.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Args {
+        Args::parse(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    #[test]
+    fn keeps_the_dots_of_the_stem() {
+        let args = args(&["-f", "png", "-o", "out", "in"]);
+        assert_eq!(
+            output_path(Path::new("photos/IMG.2024.v1.jpg"), &args),
+            Path::new("out/IMG.2024.v1.png")
+        );
+        assert_eq!(
+            output_path(Path::new("photos/IMG"), &args),
+            Path::new("out/IMG.png")
+        );
+    }
+
+    #[test]
+    fn dotted_stems_do_not_collide() {
+        let args = args(&["-o", "out", "in"]);
+        let inputs = [
+            PathBuf::from("IMG.2024.v1.jpg"),
+            PathBuf::from("IMG.2024.v2.jpg"),
+        ];
+        assert!(check_output_names(&inputs, &args).is_ok());
+        let inputs = [PathBuf::from("a/IMG.jpg"), PathBuf::from("b/IMG.png")];
+        assert!(check_output_names(&inputs, &args).is_err());
+    }
+}
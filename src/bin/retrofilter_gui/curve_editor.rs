@@ -0,0 +1,190 @@
+use crate::Message;
+use fltk::{
+    app, draw,
+    enums::{Color, Event, FrameType},
+    frame::Frame,
+    prelude::*,
+};
+use retro_filter::{Spline, ToneCurve, ToneCurveSettings};
+use std::{cell::RefCell, rc::Rc};
+
+// distance in pixels within which a click grabs a point
+const GRAB_DISTANCE: f32 = 6.0;
+// closest two points can get on the x axis
+const MIN_SPACING: f32 = 0.01;
+
+pub const CHANNELS: &str = "Master|Red|Green|Blue";
+const CHANNEL_COLORS: [Color; 4] = [Color::Black, Color::Red, Color::DarkGreen, Color::Blue];
+
+// the control points of the master, red, green and blue curve, the curve of the selected channel
+// is edited
+#[derive(Debug, Clone)]
+pub struct Curves {
+    pub points: [Vec<(f32, f32)>; 4],
+    pub channel: usize,
+    dragged: Option<usize>,
+}
+impl Curves {
+    pub fn set(&mut self, settings: &ToneCurveSettings) {
+        self.points = [
+            settings.master.clone(),
+            settings.red.clone(),
+            settings.green.clone(),
+            settings.blue.clone(),
+        ];
+        self.dragged = None;
+    }
+    pub fn reset_channel(&mut self) {
+        self.points[self.channel] = ToneCurve::IDENTITY.to_vec();
+        self.dragged = None;
+    }
+}
+
+// a square frame showing the curves, clicking adds a point, dragging moves it and a right click
+// removes it, every change sends the message
+pub fn curve_editor(
+    x: i32,
+    y: i32,
+    size: i32,
+    sender: app::Sender<Message>,
+    message: Message,
+) -> (Frame, Rc<RefCell<Curves>>) {
+    let curves = Rc::new(RefCell::new(Curves {
+        points: std::array::from_fn(|_| ToneCurve::IDENTITY.to_vec()),
+        channel: 0,
+        dragged: None,
+    }));
+    let mut frame = Frame::new(x, y, size, size, None);
+    frame.set_frame(FrameType::DownBox);
+    frame.set_color(Color::White);
+    frame.set_tooltip("Click to add a point, drag to move it, right click to remove it");
+
+    let draw_curves = Rc::clone(&curves);
+    frame.draw(move |frame| draw_curves_frame(frame, &draw_curves.borrow()));
+
+    let handle_curves = Rc::clone(&curves);
+    frame.handle(move |frame, event| {
+        let mut curves = handle_curves.borrow_mut();
+        let curves = &mut *curves;
+        let channel = curves.channel;
+        match event {
+            Event::Push => {
+                let (x, y) = event_position(frame);
+                let grab = GRAB_DISTANCE / frame.w() as f32;
+                let grabbed = curves.points[channel]
+                    .iter()
+                    .position(|point| (point.0 - x).hypot(point.1 - y) <= grab);
+                let points = &mut curves.points[channel];
+                let button = app::event_mouse_button();
+                match grabbed {
+                    // a curve needs at least two points
+                    Some(index) if button == app::MouseButton::Right && points.len() > 2 => {
+                        points.remove(index);
+                        sender.send(message);
+                    }
+                    Some(_) if button == app::MouseButton::Right => {}
+                    Some(index) => curves.dragged = Some(index),
+                    None if button == app::MouseButton::Left => {
+                        let index = points.partition_point(|point| point.0 < x);
+                        let free = [index.checked_sub(1), Some(index)]
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|index| points.get(index))
+                            .all(|point| (point.0 - x).abs() >= MIN_SPACING);
+                        if free {
+                            points.insert(index, (x, y));
+                            curves.dragged = Some(index);
+                            sender.send(message);
+                        }
+                    }
+                    None => {}
+                }
+                frame.redraw();
+                true
+            }
+            Event::Drag => {
+                if let Some(index) = curves.dragged {
+                    let (x, y) = event_position(frame);
+                    let points = &mut curves.points[channel];
+                    // points can not pass their neighbours
+                    let low = match index {
+                        0 => 0.0,
+                        _ => points[index - 1].0 + MIN_SPACING,
+                    };
+                    let high = match points.get(index + 1) {
+                        Some(point) => point.0 - MIN_SPACING,
+                        None => 1.0,
+                    };
+                    points[index] = (x.clamp(low, high.max(low)), y);
+                    frame.redraw();
+                    sender.send(message);
+                }
+                true
+            }
+            Event::Released => {
+                curves.dragged = None;
+                true
+            }
+            _ => false,
+        }
+    });
+    (frame, curves)
+}
+
+// the position of the mouse on the curve, y goes up
+fn event_position(frame: &Frame) -> (f32, f32) {
+    let x = (app::event_x() - frame.x()) as f32 / frame.w() as f32;
+    let y = 1.0 - (app::event_y() - frame.y()) as f32 / frame.h() as f32;
+    (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0))
+}
+
+fn draw_curves_frame(frame: &Frame, curves: &Curves) {
+    let (x, y, w, h) = (frame.x(), frame.y(), frame.w(), frame.h());
+    let color = |color: Color| {
+        if frame.active_r() {
+            color
+        } else {
+            color.inactive()
+        }
+    };
+    draw::draw_box(frame.frame(), x, y, w, h, color(frame.color()));
+    draw::push_clip(x, y, w, h);
+    // quarter grid and the diagonal of the unchanged curve
+    draw::set_draw_color(color(Color::Light1));
+    for step in 1..4 {
+        draw::draw_line(x + w * step / 4, y, x + w * step / 4, y + h);
+        draw::draw_line(x, y + h * step / 4, x + w, y + h * step / 4);
+    }
+    draw::draw_line(x, y + h, x + w, y);
+    // the other channels stay visible behind the edited one
+    let channels = (0..4).filter(|channel| *channel != curves.channel);
+    for channel in channels.chain([curves.channel]) {
+        let spline = match Spline::new(&curves.points[channel]) {
+            Ok(spline) => spline,
+            Err(_) => continue,
+        };
+        let active = channel == curves.channel;
+        let mut line_color = CHANNEL_COLORS[channel];
+        if !active {
+            line_color = line_color.lighter().lighter();
+        }
+        draw::set_draw_color(color(line_color));
+        draw::begin_line();
+        for column in 0..=w {
+            let value = spline.value(column as f32 / w as f32);
+            draw::vertex(
+                (x + column) as f64,
+                y as f64 + (1.0 - value as f64) * h as f64,
+            );
+        }
+        draw::end_line();
+        if active {
+            for (px, py) in &curves.points[channel] {
+                let px = x + (px * w as f32).round() as i32;
+                let py = y + ((1.0 - py) * h as f32).round() as i32;
+                draw::draw_rectf(px - 3, py - 3, 7, 7);
+            }
+        }
+    }
+    draw::pop_clip();
+}
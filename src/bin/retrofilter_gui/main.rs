@@ -1,25 +1,31 @@
-mod filehandling;
-use filehandling::{image_open, image_save};
+mod curve_editor;
+use curve_editor::{curve_editor, Curves};
 use fltk::{
     app,
     button::{self, CheckButton},
     dialog::{self, FileDialogOptions},
-    enums::{Align, Color, ColorDepth, FrameType},
+    enums::{Align, Color, ColorDepth, Event, FrameType},
     frame,
     frame::Frame,
     group::Group,
     image as fl_image,
+    menu::Choice,
     prelude::*,
     valuator,
     valuator::NiceSlider,
     window,
 };
 use fltk_theme::{ThemeType, WidgetTheme};
-use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb};
-use palette::Blend;
-use retro_filter::{bleach_bypass, create_vignette, film_grain, palette_blend};
+use image::{DynamicImage, GenericImageView};
+use retro_filter::{
+    image_open_with_metadata, image_save_with_metadata, BleachBypassSettings, BlendMode,
+    ColorGradeSettings, Context, ExportFormat, FilmStock, FilmStockSettings, GrainColor,
+    GrainSettings, Lut, LutInterpolation, LutSettings, Metadata, Pipeline, Preset, Tint,
+    ToneCurveSettings, VignetteCurve, VignetteSettings, VignetteShape,
+};
 use std::{
-    path::PathBuf,
+    path::{Path, PathBuf},
+    rc::Rc,
     sync::{Arc, Mutex},
     thread,
 };
@@ -29,128 +35,396 @@ pub enum Message {
     OpenFile,
     SaveFile,
     ProcessFile,
+    FilmStockChange,
+    FilmStockToggle,
+    LutLoad,
+    LutChange,
+    LutToggle,
+    ToneCurveChange,
+    ToneCurveToggle,
+    ColorGradeChange,
+    ColorGradeToggle,
     VignetteChange,
     VignetteToggle,
     FilmgrainChange,
     FilmgrainToggle,
     BleachbypassChange,
     BleachbypassToggle,
+    TransparencyToggle,
+    // position of a click relative to the center of the preview frame
+    PreviewClick(i32, i32),
+    ProcessFailed,
+    LoadPreset,
+    SavePreset,
 }
 
-#[allow(clippy::type_complexity)]
 #[derive(Debug, Clone)]
 struct DataState {
     image_data: Option<DynamicImage>,
+    metadata: Metadata,
     preview_size: u32,
     image_thumbnail: Option<DynamicImage>,
-    // Todo: Change this to type definition at some point
-    image_processed: Arc<Mutex<Option<ImageBuffer<Rgb<u8>, Vec<u8>>>>>,
+    image_processed: Arc<Mutex<Option<DynamicImage>>>,
+    process_error: Arc<Mutex<Option<String>>>,
 }
 impl DataState {
     fn new() -> Self {
         Self {
             image_data: None,
+            metadata: Metadata::default(),
             preview_size: 400,
             image_thumbnail: None,
             image_processed: Arc::new(Mutex::new(None)),
+            process_error: Arc::new(Mutex::new(None)),
         }
     }
-    fn set_image(&mut self, input_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-        self.image_data = Some(image_open(input_path)?);
-        if let Some(image) = &self.image_data {
-            self.image_thumbnail = Some(image.thumbnail(self.preview_size, self.preview_size));
-        }
+    fn set_image(&mut self, input_path: PathBuf) -> Result<(), retro_filter::Error> {
+        // the current image is only replaced once the new one could be decoded
+        let (image, metadata) = image_open_with_metadata(input_path)?;
+        self.image_thumbnail = Some(image.thumbnail(self.preview_size, self.preview_size));
+        self.image_data = Some(image);
+        self.metadata = metadata;
         Ok(())
     }
-    fn process_thumbnail(&mut self, input_state: &InputState) {
+    fn process_thumbnail(&mut self, input_state: &InputState) -> Result<(), retro_filter::Error> {
         if let Some(image_thumbnail) = &self.image_thumbnail {
-            self.image_thumbnail = Some(DynamicImage::ImageRgb8(process_image(
-                image_thumbnail,
-                input_state,
-                self.preview_size,
-                true,
-            )));
+            self.image_thumbnail = Some(process_image(image_thumbnail, input_state)?);
         }
+        Ok(())
     }
     fn reset_thumbnail(&mut self) {
         if let Some(image) = &self.image_data {
             self.image_thumbnail = Some(image.thumbnail(self.preview_size, self.preview_size));
         }
     }
+    // redraws the preview, if the effects fail the unprocessed thumbnail is shown instead
+    fn update_preview(&mut self, input_state: &InputState, frame: &mut Frame) {
+        self.reset_thumbnail();
+        if let Err(err) = self.process_thumbnail(input_state) {
+            self.reset_thumbnail();
+            show_error("Could not render the preview", &err);
+        }
+        if let Err(err) = self.set_fltk_image(frame) {
+            show_error("Could not display the preview", &err);
+        }
+    }
     fn process_image(
         &mut self,
         input_state: &InputState,
         process_button: &mut button::LightButton,
         save_button: &mut button::Button,
+        sender: app::Sender<Message>,
     ) {
-        let image_data = self.image_data.clone();
-        let preview_size = self.preview_size;
+        let image_data = match &self.image_data {
+            Some(image_data) => image_data.clone(),
+            None => return,
+        };
 
         let input_state = input_state.clone();
         let mut process_button = process_button.clone();
         let mut save_button = save_button.clone();
 
         let processed_image = Arc::clone(&self.image_processed);
+        let process_error = Arc::clone(&self.process_error);
 
         thread::spawn(move || {
             process_button.turn_on(true);
-            let mut processed_image = processed_image.lock().unwrap();
-            *processed_image = Some(process_image(
-                image_data.as_ref().unwrap(),
-                &input_state,
-                preview_size,
-                false,
-            ));
+            // the lock is only held to swap in the result, so the image can be saved while the
+            // next one is processed
+            match process_image(&image_data, &input_state) {
+                Ok(image) => {
+                    *processed_image.lock().unwrap() = Some(image);
+                    save_button.activate();
+                }
+                Err(err) => {
+                    // a stale result must not be saved with the failed settings
+                    *processed_image.lock().unwrap() = None;
+                    save_button.deactivate();
+                    // errors can only be shown from the main thread
+                    *process_error.lock().unwrap() = Some(err.to_string());
+                    sender.send(Message::ProcessFailed);
+                }
+            }
             process_button.turn_on(false);
-            save_button.activate();
         });
     }
     fn set_fltk_image(&mut self, frame: &mut Frame) -> Result<(), FltkError> {
         if let Some(thumbnail) = &self.image_thumbnail {
             let (w, h) = thumbnail.dimensions();
+            // rgba so transparent areas show the frame background
             let fltk_img = fl_image::RgbImage::new(
-                &thumbnail.to_rgb8(),
+                &thumbnail.to_rgba8(),
                 w as i32,
                 h as i32,
-                ColorDepth::Rgb8,
+                ColorDepth::Rgba8,
             )?;
             frame.set_image(Some(fltk_img));
         }
         Ok(())
     }
+    // the thumbnail is drawn in the center of the preview frame, returns the position relative
+    // to the image size if it is inside the image
+    fn preview_position(&self, x: i32, y: i32) -> Option<(f32, f32)> {
+        let (w, h) = self.image_thumbnail.as_ref()?.dimensions();
+        let x = x as f32 / w as f32 + 0.5;
+        let y = y as f32 / h as f32 + 0.5;
+        ((0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y)).then_some((x, y))
+    }
 }
 
 #[derive(Clone, Debug)]
 struct InputState {
-    vignette: Option<(f64, f64)>,
-    filmgrain: Option<(f64, f64)>,
-    bleachbypass: Option<(f64, f64)>,
+    preset: Preset,
+    // kept while the vignette is turned off
+    vignette_center: (f32, f32),
+    // the loaded lut and its file, read once instead of on every preview
+    lut: Option<(PathBuf, Arc<Lut>)>,
 }
 impl InputState {
     fn new() -> InputState {
+        // the seed is kept for the whole session so the preview and the processed image match
         InputState {
-            vignette: None,
-            filmgrain: None,
-            bleachbypass: None,
+            preset: Preset {
+                seed: Some(rand::random()),
+                ..Preset::default()
+            },
+            vignette_center: (0.5, 0.5),
+            lut: None,
         }
     }
-    fn set_vignette(&mut self, slider_radius: &NiceSlider, slider_alpha: &NiceSlider) {
-        self.vignette = Some((slider_radius.value(), slider_alpha.value()));
+    fn set_film_stock(
+        &mut self,
+        choice_stock: &Choice,
+        slider_alpha: &NiceSlider,
+        choice_blend: &Choice,
+    ) {
+        let stock = FilmStock::ALL[choice_stock.value().max(0) as usize];
+        self.preset.film_stock = Some(FilmStockSettings {
+            stock,
+            opacity: slider_alpha.value() as f32,
+            blend_mode: blend_mode(choice_blend),
+        });
+    }
+    fn reset_film_stock(&mut self) {
+        self.preset.film_stock = None;
+    }
+    // the lut is only used once a file was loaded
+    fn set_lut(
+        &mut self,
+        choice_interpolation: &Choice,
+        slider_alpha: &NiceSlider,
+        choice_blend: &Choice,
+    ) {
+        self.preset.lut = self.lut.as_ref().map(|(path, _)| LutSettings {
+            path: path.clone(),
+            opacity: slider_alpha.value() as f32,
+            interpolation: match choice_interpolation.value() {
+                1 => LutInterpolation::Trilinear,
+                _ => LutInterpolation::Tetrahedral,
+            },
+            blend_mode: blend_mode(choice_blend),
+        });
+    }
+    fn reset_lut(&mut self) {
+        self.preset.lut = None;
+    }
+    fn set_tone_curve(
+        &mut self,
+        curves: &Curves,
+        slider_alpha: &NiceSlider,
+        choice_blend: &Choice,
+    ) {
+        let [master, red, green, blue] = curves.points.clone();
+        self.preset.tone_curve = Some(ToneCurveSettings {
+            opacity: slider_alpha.value() as f32,
+            master,
+            red,
+            green,
+            blue,
+            blend_mode: blend_mode(choice_blend),
+        });
+    }
+    fn reset_tone_curve(&mut self) {
+        self.preset.tone_curve = None;
+    }
+    // the sliders are the hue and the saturation of the shadows, midtones and highlights
+    fn set_color_grade(
+        &mut self,
+        sliders_tint: [&NiceSlider; 6],
+        slider_balance: &NiceSlider,
+        slider_alpha: &NiceSlider,
+        choice_blend: &Choice,
+    ) {
+        let [shadows, midtones, highlights] = [0, 2, 4].map(|index| Tint {
+            hue: sliders_tint[index].value() as f32,
+            saturation: sliders_tint[index + 1].value() as f32,
+        });
+        self.preset.color_grade = Some(ColorGradeSettings {
+            opacity: slider_alpha.value() as f32,
+            balance: slider_balance.value() as f32,
+            blend_mode: blend_mode(choice_blend),
+            shadows,
+            midtones,
+            highlights,
+        });
+    }
+    fn reset_color_grade(&mut self) {
+        self.preset.color_grade = None;
+    }
+    fn set_vignette(
+        &mut self,
+        slider_radius: &NiceSlider,
+        slider_alpha: &NiceSlider,
+        choice_shape: &Choice,
+        slider_roundness: &NiceSlider,
+        choice_curve: &Choice,
+        choice_blend: &Choice,
+    ) {
+        let shape = match choice_shape.value() {
+            1 => VignetteShape::Ellipse,
+            2 => VignetteShape::RoundedRect {
+                roundness: slider_roundness.value() as f32,
+            },
+            _ => VignetteShape::Circle,
+        };
+        let curve = match choice_curve.value() {
+            1 => VignetteCurve::Smoothstep,
+            2 => VignetteCurve::Cos4,
+            _ => VignetteCurve::Linear,
+        };
+        self.preset.vignette = Some(VignetteSettings {
+            radius: slider_radius.value(),
+            opacity: slider_alpha.value() as f32,
+            center: self.vignette_center,
+            curve,
+            noise: true,
+            blend_mode: blend_mode(choice_blend),
+            shape,
+        });
     }
     fn reset_vignette(&mut self) {
-        self.vignette = None;
+        self.preset.vignette = None;
     }
-    fn set_filmgrain(&mut self, slider_strength: &NiceSlider, slider_alpha: &NiceSlider) {
-        self.filmgrain = Some((slider_strength.value(), slider_alpha.value()));
+    fn set_filmgrain(
+        &mut self,
+        slider_intensity: &NiceSlider,
+        slider_alpha: &NiceSlider,
+        slider_size: &NiceSlider,
+        slider_roughness: &NiceSlider,
+        color: GrainColor,
+        choice_blend: &Choice,
+    ) {
+        self.preset.grain = Some(GrainSettings {
+            intensity: slider_intensity.value() as f32,
+            opacity: slider_alpha.value() as f32,
+            size: slider_size.value() as f32,
+            roughness: slider_roughness.value() as f32,
+            blend_mode: blend_mode(choice_blend),
+            color,
+        });
     }
     fn reset_filmgrain(&mut self) {
-        self.filmgrain = None;
+        self.preset.grain = None;
     }
-    fn set_bleachbypass(&mut self, slider_strength: &NiceSlider, slider_alpha: &NiceSlider) {
-        self.bleachbypass = Some((slider_strength.value(), slider_alpha.value()));
+    fn set_bleachbypass(
+        &mut self,
+        slider_strength: &NiceSlider,
+        slider_alpha: &NiceSlider,
+        sliders_tone: [&NiceSlider; 4],
+        choice_blend: &Choice,
+    ) {
+        let [contrast, silver_retention, shadow_rolloff, highlight_rolloff] =
+            sliders_tone.map(|slider| slider.value() as f32);
+        self.preset.bleach_bypass = Some(BleachBypassSettings {
+            blur: slider_strength.value() as f32,
+            opacity: slider_alpha.value() as f32,
+            contrast,
+            silver_retention,
+            shadow_rolloff,
+            highlight_rolloff,
+            blend_mode: blend_mode(choice_blend),
+        });
     }
     fn reset_bleachbypass(&mut self) {
-        self.bleachbypass = None;
+        self.preset.bleach_bypass = None;
+    }
+    fn pipeline(&self) -> Result<Pipeline, retro_filter::Error> {
+        let lut = self.lut.as_ref().map(|(_, lut)| Arc::clone(lut));
+        Ok(self.preset.pipeline_with_lut(lut))
+    }
+}
+
+// shows the settings of an effect group or deactivates it if the effect is not part of a preset
+fn set_effect_controls<const N: usize>(
+    controls: &mut Group,
+    active: &mut CheckButton,
+    sliders: [(&mut NiceSlider, f64); N],
+    enabled: bool,
+) {
+    if enabled {
+        controls.activate();
+        for (slider, value) in sliders {
+            slider.set_value(value);
+        }
+    } else {
+        controls.deactivate();
+    }
+    active.set_checked(enabled);
+}
+
+fn grain_color(
+    choice_color: &Choice,
+    slider_saturation: &NiceSlider,
+    sliders_amplitude: [&NiceSlider; 3],
+) -> GrainColor {
+    match choice_color.value() {
+        1 => GrainColor::Chroma {
+            amplitude: sliders_amplitude.map(|slider| slider.value() as f32),
+            saturation: slider_saturation.value() as f32,
+        },
+        _ => GrainColor::Monochrome,
+    }
+}
+
+fn set_chroma_controls(sliders: [&mut NiceSlider; 4], chroma: Option<[f64; 4]>) {
+    for (index, slider) in sliders.into_iter().enumerate() {
+        match chroma {
+            Some(values) => {
+                slider.set_value(values[index]);
+                slider.activate();
+            }
+            None => slider.deactivate(),
+        }
+    }
+}
+
+// a choice with all blend modes in the order of BlendMode::ALL
+fn blend_choice(x: i32, y: i32, mode: BlendMode) -> Choice {
+    let mut choice = Choice::new(x, y, 110, 20, None);
+    for mode in BlendMode::ALL {
+        choice.add_choice(mode.name());
+    }
+    set_blend_choice(&mut choice, mode);
+    choice.set_tooltip("Blend mode");
+    choice
+}
+
+fn blend_mode(choice: &Choice) -> BlendMode {
+    BlendMode::ALL[choice.value().max(0) as usize]
+}
+
+fn set_blend_choice(choice: &mut Choice, mode: BlendMode) {
+    let index = BlendMode::ALL.iter().position(|other| *other == mode);
+    choice.set_value(index.unwrap_or_default() as i32);
+}
+
+fn set_roundness_control(slider: &mut NiceSlider, roundness: Option<f64>) {
+    match roundness {
+        Some(roundness) => {
+            slider.set_value(roundness);
+            slider.activate();
+        }
+        None => slider.deactivate(),
     }
 }
 
@@ -171,7 +445,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // define window
     let mut win = window::Window::default()
-        .with_size(810, 500)
+        .with_size(1590, 500)
         .center_screen()
         .with_label("Retro Filter");
     win.set_color(Color::BackGround);
@@ -181,44 +455,102 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut input_chooser = dialog::NativeFileChooser::new(dialog::FileDialogType::BrowseFile);
     input_chooser.set_option(dialog::FileDialogOptions::Preview);
     input_chooser.set_option(FileDialogOptions::UseFilterExt);
-    input_chooser.set_filter("*.{jpg,jpeg,JPG,png,tif,bmp}");
+    input_chooser.set_filter("*.{jpg,jpeg,JPG,png,tif,tiff,bmp,webp}");
     let mut save_chooser = dialog::NativeFileChooser::new(dialog::FileDialogType::BrowseSaveFile);
     save_chooser.set_option(dialog::FileDialogOptions::SaveAsConfirm);
+    save_chooser.set_filter(EXPORT_FILTER);
     let mut btn_open_file = button::Button::new(10, 420, 100, 20, "Open File");
     let mut btn_save_file = button::Button::new(10, 450, 100, 20, "Save File");
     btn_save_file.deactivate();
     let mut btn_process_file = button::LightButton::new(120, 420, 100, 20, "Process");
     btn_process_file.deactivate();
     btn_process_file.turn_on(false);
+    let strip_gps = CheckButton::new(10, 480, 100, 20, "Strip GPS");
     let mut slider_jpg_quality = valuator::HorValueSlider::new(120, 450, 100, 20, "JPG Quality");
     slider_jpg_quality.set_range(1.0, 100.0);
     slider_jpg_quality.set_step(1.0, 1);
     slider_jpg_quality.set_value(75.0);
+    let mut preset_open_chooser =
+        dialog::NativeFileChooser::new(dialog::FileDialogType::BrowseFile);
+    preset_open_chooser.set_filter("*.toml");
+    let mut preset_save_chooser =
+        dialog::NativeFileChooser::new(dialog::FileDialogType::BrowseSaveFile);
+    preset_save_chooser.set_option(dialog::FileDialogOptions::SaveAsConfirm);
+    preset_save_chooser.set_filter("*.toml");
+    let mut btn_load_preset = button::Button::new(230, 420, 100, 20, "Load Look");
+    let mut btn_save_preset = button::Button::new(230, 450, 100, 20, "Save Look");
+    let mut affect_transparent = CheckButton::new(230, 480, 180, 20, "Effects on transparency");
     let mut preview_frame =
         frame::Frame::new(10, 10, preview_size as i32, preview_size as i32, None);
     preview_frame.set_frame(FrameType::BorderBox);
     preview_frame.set_color(Color::Dark3);
+    preview_frame.set_tooltip("Click to place the center of the vignette");
+    preview_frame.handle(move |frame, event| match event {
+        Event::Push => {
+            s.send(Message::PreviewClick(
+                app::event_x() - frame.x() - frame.w() / 2,
+                app::event_y() - frame.y() - frame.h() / 2,
+            ));
+            true
+        }
+        _ => false,
+    });
 
     // vignette controls
     let mut vignette_controls = Group::new(420, 10, 120, 400, "Vignette");
     vignette_controls.set_align(Align::BottomRight);
     vignette_controls.set_frame(FrameType::BorderBox);
     let mut slider_vignette_radius = valuator::NiceSlider::default()
-        .with_size(20, 370)
-        .with_pos(vignette_controls.x() + 20, vignette_controls.y() + 10)
+        .with_size(20, 270)
+        .with_pos(vignette_controls.x() + 10, vignette_controls.y() + 10)
         .with_label("Radius");
-    slider_vignette_radius.set_range(350.0, 50.0);
-    slider_vignette_radius.set_step(1.0, 1);
-    slider_vignette_radius.set_value(250.0);
+    slider_vignette_radius.set_range(0.75, 0.1);
+    slider_vignette_radius.set_step(0.01, 1);
+    slider_vignette_radius.set_value(0.52);
     let mut slider_vignette_alpha = valuator::NiceSlider::default()
-        .with_size(20, 370)
-        .with_pos(vignette_controls.x() + 80, vignette_controls.y() + 10)
+        .with_size(20, 270)
+        .with_pos(vignette_controls.x() + 50, vignette_controls.y() + 10)
         .with_label("Alpha");
     slider_vignette_alpha.set_range(1.0, 0.0);
     slider_vignette_alpha.set_step(0.1, 1);
     slider_vignette_alpha.set_value(0.7);
+    let mut slider_vignette_roundness = valuator::NiceSlider::default()
+        .with_size(20, 270)
+        .with_pos(vignette_controls.x() + 90, vignette_controls.y() + 10)
+        .with_label("Round");
+    slider_vignette_roundness.set_range(1.0, 0.0);
+    slider_vignette_roundness.set_step(0.1, 1);
+    slider_vignette_roundness.set_value(0.5);
+    slider_vignette_roundness.deactivate();
+    let mut choice_vignette_blend = blend_choice(
+        vignette_controls.x() + 5,
+        vignette_controls.y() + 316,
+        VignetteSettings::DEFAULT_BLEND_MODE,
+    );
+    let mut choice_vignette_shape = Choice::new(
+        vignette_controls.x() + 5,
+        vignette_controls.y() + 343,
+        110,
+        20,
+        None,
+    );
+    choice_vignette_shape.add_choice("Circle|Ellipse|Rounded rect");
+    choice_vignette_shape.set_value(0);
+    let mut choice_vignette_curve = Choice::new(
+        vignette_controls.x() + 5,
+        vignette_controls.y() + 370,
+        110,
+        20,
+        None,
+    );
+    choice_vignette_curve.add_choice("Linear|Smoothstep|Lens (cos⁴)");
+    choice_vignette_curve.set_value(0);
     slider_vignette_radius.emit(s, Message::VignetteChange);
     slider_vignette_alpha.emit(s, Message::VignetteChange);
+    slider_vignette_roundness.emit(s, Message::VignetteChange);
+    choice_vignette_shape.emit(s, Message::VignetteChange);
+    choice_vignette_curve.emit(s, Message::VignetteChange);
+    choice_vignette_blend.emit(s, Message::VignetteChange);
     vignette_controls.end();
     vignette_controls.deactivate();
     let mut vignette_active = CheckButton::default()
@@ -230,22 +562,87 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut filmgrain_controls = Group::new(550, 10, 120, 400, "Filmgrain");
     filmgrain_controls.set_align(Align::BottomRight);
     filmgrain_controls.set_frame(FrameType::BorderBox);
-    let mut slider_filmgrain_strength = valuator::NiceSlider::default()
-        .with_size(20, 370)
-        .with_pos(filmgrain_controls.x() + 20, filmgrain_controls.y() + 10)
-        .with_label("Strength");
-    slider_filmgrain_strength.set_range(100.0, 0.0);
-    slider_filmgrain_strength.set_step(1.0, 1);
-    slider_filmgrain_strength.set_value(50.0);
+    let mut slider_filmgrain_intensity = valuator::NiceSlider::default()
+        .with_size(20, 75)
+        .with_pos(filmgrain_controls.x() + 10, filmgrain_controls.y() + 10)
+        .with_label("Inten");
+    slider_filmgrain_intensity.set_range(1.0, 0.0);
+    slider_filmgrain_intensity.set_step(0.05, 1);
+    slider_filmgrain_intensity.set_value(0.5);
     let mut slider_filmgrain_alpha = valuator::NiceSlider::default()
-        .with_size(20, 370)
-        .with_pos(filmgrain_controls.x() + 80, filmgrain_controls.y() + 10)
+        .with_size(20, 75)
+        .with_pos(filmgrain_controls.x() + 50, filmgrain_controls.y() + 10)
         .with_label("Alpha");
     slider_filmgrain_alpha.set_range(1.0, 0.0);
     slider_filmgrain_alpha.set_step(0.1, 1);
     slider_filmgrain_alpha.set_value(0.2);
-    slider_filmgrain_strength.emit(s, Message::FilmgrainChange);
+    let mut slider_filmgrain_size = valuator::NiceSlider::default()
+        .with_size(20, 75)
+        .with_pos(filmgrain_controls.x() + 90, filmgrain_controls.y() + 10)
+        .with_label("Size");
+    slider_filmgrain_size.set_range(0.004, 0.0005);
+    slider_filmgrain_size.set_step(0.0001, 1);
+    slider_filmgrain_size.set_value(GrainSettings::DEFAULT_SIZE as f64);
+    let mut slider_filmgrain_roughness = valuator::NiceSlider::default()
+        .with_size(20, 75)
+        .with_pos(filmgrain_controls.x() + 10, filmgrain_controls.y() + 110)
+        .with_label("Rough");
+    slider_filmgrain_roughness.set_range(1.0, 0.0);
+    slider_filmgrain_roughness.set_step(0.1, 1);
+    slider_filmgrain_roughness.set_value(GrainSettings::DEFAULT_ROUGHNESS as f64);
+    let mut slider_filmgrain_saturation = valuator::NiceSlider::default()
+        .with_size(20, 75)
+        .with_pos(filmgrain_controls.x() + 50, filmgrain_controls.y() + 110)
+        .with_label("Sat");
+    slider_filmgrain_saturation.set_range(1.0, 0.0);
+    slider_filmgrain_saturation.set_step(0.1, 1);
+    slider_filmgrain_saturation.set_value(GrainColor::DEFAULT_SATURATION as f64);
+    slider_filmgrain_saturation.deactivate();
+    let [mut slider_filmgrain_red, mut slider_filmgrain_green, mut slider_filmgrain_blue] =
+        [("R", 10), ("G", 50), ("B", 90)].map(|(label, x)| {
+            let mut slider = valuator::NiceSlider::default()
+                .with_size(20, 75)
+                .with_pos(filmgrain_controls.x() + x, filmgrain_controls.y() + 210)
+                .with_label(label);
+            slider.set_range(1.0, 0.0);
+            slider.set_step(0.1, 1);
+            slider.deactivate();
+            slider
+        });
+    for (slider, amplitude) in [
+        &mut slider_filmgrain_red,
+        &mut slider_filmgrain_green,
+        &mut slider_filmgrain_blue,
+    ]
+    .into_iter()
+    .zip(GrainColor::DEFAULT_AMPLITUDE)
+    {
+        slider.set_value(amplitude as f64);
+    }
+    let mut choice_filmgrain_blend = blend_choice(
+        filmgrain_controls.x() + 5,
+        filmgrain_controls.y() + 343,
+        BlendMode::default(),
+    );
+    let mut choice_filmgrain_color = Choice::new(
+        filmgrain_controls.x() + 5,
+        filmgrain_controls.y() + 370,
+        110,
+        20,
+        None,
+    );
+    choice_filmgrain_color.add_choice("Monochrome|Chroma");
+    choice_filmgrain_color.set_value(0);
+    slider_filmgrain_intensity.emit(s, Message::FilmgrainChange);
     slider_filmgrain_alpha.emit(s, Message::FilmgrainChange);
+    slider_filmgrain_size.emit(s, Message::FilmgrainChange);
+    slider_filmgrain_roughness.emit(s, Message::FilmgrainChange);
+    slider_filmgrain_saturation.emit(s, Message::FilmgrainChange);
+    slider_filmgrain_red.emit(s, Message::FilmgrainChange);
+    slider_filmgrain_green.emit(s, Message::FilmgrainChange);
+    slider_filmgrain_blue.emit(s, Message::FilmgrainChange);
+    choice_filmgrain_color.emit(s, Message::FilmgrainChange);
+    choice_filmgrain_blend.emit(s, Message::FilmgrainChange);
     filmgrain_controls.end();
     filmgrain_controls.deactivate();
     let mut filmgrain_active = CheckButton::default()
@@ -258,27 +655,65 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     bleachbypass_controls.set_align(Align::BottomRight);
     bleachbypass_controls.set_frame(FrameType::BorderBox);
     let mut slider_bleachbypass_blur = valuator::NiceSlider::default()
-        .with_size(20, 370)
+        .with_size(20, 140)
         .with_pos(
-            bleachbypass_controls.x() + 20,
+            bleachbypass_controls.x() + 10,
             bleachbypass_controls.y() + 10,
         )
         .with_label("Blur");
-    slider_bleachbypass_blur.set_range(2.0, 0.1);
-    slider_bleachbypass_blur.set_step(0.1, 1);
+    slider_bleachbypass_blur.set_range(0.004, 0.0);
+    slider_bleachbypass_blur.set_step(0.0002, 1);
     slider_bleachbypass_blur.set_value(0.0);
     let mut slider_bleachbypass_alpha = valuator::NiceSlider::default()
-        .with_size(20, 370)
+        .with_size(20, 140)
         .with_pos(
-            bleachbypass_controls.x() + 80,
+            bleachbypass_controls.x() + 50,
             bleachbypass_controls.y() + 10,
         )
         .with_label("Alpha");
     slider_bleachbypass_alpha.set_range(1.0, 0.0);
     slider_bleachbypass_alpha.set_step(0.1, 1);
     slider_bleachbypass_alpha.set_value(0.2);
+    let mut slider_bleachbypass_contrast = valuator::NiceSlider::default()
+        .with_size(20, 140)
+        .with_pos(
+            bleachbypass_controls.x() + 90,
+            bleachbypass_controls.y() + 10,
+        )
+        .with_label("Contr");
+    slider_bleachbypass_contrast.set_range(1.0, 0.0);
+    slider_bleachbypass_contrast.set_step(0.05, 1);
+    slider_bleachbypass_contrast.set_value(BleachBypassSettings::DEFAULT_CONTRAST as f64);
+    let [mut slider_bleachbypass_silver, mut slider_bleachbypass_shadows, mut slider_bleachbypass_highlights] =
+        [("Silver", 10), ("Shad", 50), ("High", 90)].map(|(label, x)| {
+            let mut slider = valuator::NiceSlider::default()
+                .with_size(20, 140)
+                .with_pos(
+                    bleachbypass_controls.x() + x,
+                    bleachbypass_controls.y() + 180,
+                )
+                .with_label(label);
+            slider.set_range(1.0, 0.0);
+            slider.set_step(0.05, 1);
+            slider.set_value(0.0);
+            slider
+        });
+    slider_bleachbypass_silver.set_value(BleachBypassSettings::DEFAULT_SILVER_RETENTION as f64);
+    // the rolloffs may add up to at most 1
+    slider_bleachbypass_shadows.set_range(0.5, 0.0);
+    slider_bleachbypass_highlights.set_range(0.5, 0.0);
+    let mut choice_bleachbypass_blend = blend_choice(
+        bleachbypass_controls.x() + 5,
+        bleachbypass_controls.y() + 370,
+        BleachBypassSettings::DEFAULT_BLEND_MODE,
+    );
     slider_bleachbypass_blur.emit(s, Message::BleachbypassChange);
     slider_bleachbypass_alpha.emit(s, Message::BleachbypassChange);
+    slider_bleachbypass_contrast.emit(s, Message::BleachbypassChange);
+    slider_bleachbypass_silver.emit(s, Message::BleachbypassChange);
+    slider_bleachbypass_shadows.emit(s, Message::BleachbypassChange);
+    slider_bleachbypass_highlights.emit(s, Message::BleachbypassChange);
+    choice_bleachbypass_blend.emit(s, Message::BleachbypassChange);
     bleachbypass_controls.end();
     bleachbypass_controls.deactivate();
     let mut bleachbypass_active = CheckButton::default()
@@ -286,6 +721,223 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .below_of(&bleachbypass_controls, 10);
     bleachbypass_active.emit(s, Message::BleachbypassToggle);
 
+    // film stock controls
+    let mut film_stock_controls = Group::new(810, 10, 120, 400, "Film stock");
+    film_stock_controls.set_align(Align::BottomRight);
+    film_stock_controls.set_frame(FrameType::BorderBox);
+    let mut choice_film_stock = Choice::new(
+        film_stock_controls.x() + 5,
+        film_stock_controls.y() + 10,
+        110,
+        20,
+        None,
+    );
+    for stock in FilmStock::ALL {
+        choice_film_stock.add_choice(stock.name());
+    }
+    choice_film_stock.set_value(0);
+    let mut slider_film_stock_alpha = valuator::NiceSlider::default()
+        .with_size(20, 290)
+        .with_pos(film_stock_controls.x() + 50, film_stock_controls.y() + 50)
+        .with_label("Alpha");
+    slider_film_stock_alpha.set_range(1.0, 0.0);
+    slider_film_stock_alpha.set_step(0.1, 1);
+    slider_film_stock_alpha.set_value(1.0);
+    let mut choice_film_stock_blend = blend_choice(
+        film_stock_controls.x() + 5,
+        film_stock_controls.y() + 370,
+        BlendMode::default(),
+    );
+    choice_film_stock.emit(s, Message::FilmStockChange);
+    slider_film_stock_alpha.emit(s, Message::FilmStockChange);
+    choice_film_stock_blend.emit(s, Message::FilmStockChange);
+    film_stock_controls.end();
+    film_stock_controls.deactivate();
+    let mut film_stock_active = CheckButton::default()
+        .with_size(15, 15)
+        .below_of(&film_stock_controls, 10);
+    film_stock_active.emit(s, Message::FilmStockToggle);
+
+    // lut controls
+    let mut lut_chooser = dialog::NativeFileChooser::new(dialog::FileDialogType::BrowseFile);
+    lut_chooser.set_filter("LUT\t*.{cube,png,tif,tiff}");
+    let mut lut_controls = Group::new(940, 10, 120, 400, "LUT");
+    lut_controls.set_align(Align::BottomRight);
+    lut_controls.set_frame(FrameType::BorderBox);
+    let mut btn_load_lut = button::Button::new(
+        lut_controls.x() + 5,
+        lut_controls.y() + 10,
+        110,
+        20,
+        "Load LUT",
+    );
+    let mut lut_name = Frame::new(
+        lut_controls.x() + 5,
+        lut_controls.y() + 35,
+        110,
+        20,
+        "No LUT loaded",
+    );
+    lut_name.set_align(Align::Inside | Align::Clip);
+    let mut slider_lut_alpha = valuator::NiceSlider::default()
+        .with_size(20, 250)
+        .with_pos(lut_controls.x() + 50, lut_controls.y() + 65)
+        .with_label("Alpha");
+    slider_lut_alpha.set_range(1.0, 0.0);
+    slider_lut_alpha.set_step(0.1, 1);
+    slider_lut_alpha.set_value(1.0);
+    let mut choice_lut_blend = blend_choice(
+        lut_controls.x() + 5,
+        lut_controls.y() + 343,
+        BlendMode::default(),
+    );
+    let mut choice_lut_interpolation =
+        Choice::new(lut_controls.x() + 5, lut_controls.y() + 370, 110, 20, None);
+    choice_lut_interpolation.add_choice("Tetrahedral|Trilinear");
+    choice_lut_interpolation.set_value(0);
+    btn_load_lut.emit(s, Message::LutLoad);
+    slider_lut_alpha.emit(s, Message::LutChange);
+    choice_lut_interpolation.emit(s, Message::LutChange);
+    choice_lut_blend.emit(s, Message::LutChange);
+    lut_controls.end();
+    lut_controls.deactivate();
+    let mut lut_active = CheckButton::default()
+        .with_size(15, 15)
+        .below_of(&lut_controls, 10);
+    lut_active.emit(s, Message::LutToggle);
+
+    // tone curve controls
+    let mut tone_curve_controls = Group::new(1070, 10, 250, 400, "Tone curve");
+    tone_curve_controls.set_align(Align::BottomRight);
+    tone_curve_controls.set_frame(FrameType::BorderBox);
+    let (mut tone_curve_editor, curves) = curve_editor(
+        tone_curve_controls.x() + 10,
+        tone_curve_controls.y() + 10,
+        230,
+        s,
+        Message::ToneCurveChange,
+    );
+    let mut choice_tone_curve_channel = Choice::new(
+        tone_curve_controls.x() + 10,
+        tone_curve_controls.y() + 250,
+        110,
+        20,
+        None,
+    );
+    choice_tone_curve_channel.add_choice(curve_editor::CHANNELS);
+    choice_tone_curve_channel.set_value(0);
+    let mut btn_reset_tone_curve = button::Button::new(
+        tone_curve_controls.x() + 130,
+        tone_curve_controls.y() + 250,
+        110,
+        20,
+        "Reset channel",
+    );
+    let mut slider_tone_curve_alpha = valuator::NiceSlider::default()
+        .with_size(230, 20)
+        .with_pos(tone_curve_controls.x() + 10, tone_curve_controls.y() + 290)
+        .with_label("Alpha");
+    slider_tone_curve_alpha.set_type(valuator::SliderType::HorizontalNice);
+    slider_tone_curve_alpha.set_range(0.0, 1.0);
+    slider_tone_curve_alpha.set_step(0.1, 1);
+    slider_tone_curve_alpha.set_value(1.0);
+    let mut choice_tone_curve_blend = blend_choice(
+        tone_curve_controls.x() + 10,
+        tone_curve_controls.y() + 370,
+        BlendMode::default(),
+    );
+    // switching the channel only changes which curve is edited
+    choice_tone_curve_channel.set_callback({
+        let curves = Rc::clone(&curves);
+        let mut tone_curve_editor = tone_curve_editor.clone();
+        move |choice| {
+            curves.borrow_mut().channel = choice.value().max(0) as usize;
+            tone_curve_editor.redraw();
+        }
+    });
+    btn_reset_tone_curve.set_callback({
+        let curves = Rc::clone(&curves);
+        let mut tone_curve_editor = tone_curve_editor.clone();
+        move |_| {
+            curves.borrow_mut().reset_channel();
+            tone_curve_editor.redraw();
+            s.send(Message::ToneCurveChange);
+        }
+    });
+    slider_tone_curve_alpha.emit(s, Message::ToneCurveChange);
+    choice_tone_curve_blend.emit(s, Message::ToneCurveChange);
+    tone_curve_controls.end();
+    tone_curve_controls.deactivate();
+    let mut tone_curve_active = CheckButton::default()
+        .with_size(15, 15)
+        .below_of(&tone_curve_controls, 10);
+    tone_curve_active.emit(s, Message::ToneCurveToggle);
+
+    // color grade controls
+    let mut color_grade_controls = Group::new(1330, 10, 250, 400, "Color grade");
+    color_grade_controls.set_align(Align::BottomRight);
+    color_grade_controls.set_frame(FrameType::BorderBox);
+    let [mut slider_grade_shadow_hue, mut slider_grade_shadow_sat, mut slider_grade_mid_hue, mut slider_grade_mid_sat, mut slider_grade_high_hue, mut slider_grade_high_sat, mut slider_grade_balance, mut slider_grade_alpha] =
+        [
+            "Shadow hue",
+            "Shadow saturation",
+            "Midtone hue",
+            "Midtone saturation",
+            "Highlight hue",
+            "Highlight saturation",
+            "Balance",
+            "Alpha",
+        ]
+        .map(|label| {
+            valuator::NiceSlider::default()
+                .with_size(230, 20)
+                .with_label(label)
+        });
+    for (index, slider) in [
+        &mut slider_grade_shadow_hue,
+        &mut slider_grade_shadow_sat,
+        &mut slider_grade_mid_hue,
+        &mut slider_grade_mid_sat,
+        &mut slider_grade_high_hue,
+        &mut slider_grade_high_sat,
+        &mut slider_grade_balance,
+        &mut slider_grade_alpha,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        slider.set_type(valuator::SliderType::HorizontalNice);
+        slider.set_pos(
+            color_grade_controls.x() + 10,
+            color_grade_controls.y() + 10 + 42 * index as i32,
+        );
+        // hues in degrees, the saturations from 0 to 1
+        if index < 6 && index % 2 == 0 {
+            slider.set_range(0.0, 360.0);
+            slider.set_step(1.0, 1);
+        } else {
+            slider.set_range(0.0, 1.0);
+            slider.set_step(0.05, 1);
+        }
+        slider.set_value(0.0);
+        slider.emit(s, Message::ColorGradeChange);
+    }
+    slider_grade_balance.set_range(-1.0, 1.0);
+    slider_grade_alpha.set_step(0.1, 1);
+    slider_grade_alpha.set_value(1.0);
+    let mut choice_grade_blend = blend_choice(
+        color_grade_controls.x() + 10,
+        color_grade_controls.y() + 370,
+        BlendMode::default(),
+    );
+    choice_grade_blend.emit(s, Message::ColorGradeChange);
+    color_grade_controls.end();
+    color_grade_controls.deactivate();
+    let mut color_grade_active = CheckButton::default()
+        .with_size(15, 15)
+        .below_of(&color_grade_controls, 10);
+    color_grade_active.emit(s, Message::ColorGradeToggle);
+
     // end setup and display window
     win.end();
     win.show();
@@ -294,6 +946,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     btn_open_file.emit(s, Message::OpenFile);
     btn_process_file.emit(s, Message::ProcessFile);
     btn_save_file.emit(s, Message::SaveFile);
+    btn_load_preset.emit(s, Message::LoadPreset);
+    btn_save_preset.emit(s, Message::SavePreset);
+    affect_transparent.emit(s, Message::TransparencyToggle);
     // event loop for messages
     while app.wait() {
         if let Some(msg) = r.recv() {
@@ -303,11 +958,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     input_chooser.show();
                     let input_path = input_chooser.filename();
                     if input_path.is_file() {
-                        data_state.set_image(input_path)?;
+                        if let Err(err) = data_state.set_image(input_path.clone()) {
+                            show_error(&format!("Could not open {}", input_path.display()), &err);
+                        }
                     }
                     // draw initial view
-                    data_state.set_fltk_image(&mut preview_frame)?;
-                    btn_process_file.activate();
+                    if let Err(err) = data_state.set_fltk_image(&mut preview_frame) {
+                        show_error("Could not display the preview", &err);
+                    }
+                    if data_state.image_data.is_some() {
+                        btn_process_file.activate();
+                    }
                     app::redraw();
                 }
                 Message::ProcessFile => {
@@ -315,29 +976,517 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         &input_state,
                         &mut btn_process_file,
                         &mut btn_save_file,
+                        s,
                     );
                     app::redraw();
                 }
+                Message::ProcessFailed => {
+                    if let Some(err) = data_state.process_error.lock().unwrap().take() {
+                        show_error("Could not process the image", &err);
+                    }
+                }
                 Message::SaveFile => {
                     save_chooser.show();
                     let save_path = save_chooser.filename();
                     if save_path.file_name().is_some() {
                         let mut processed = data_state.image_processed.lock().unwrap();
-                        match &mut *processed {
-                            Some(image) => {
-                                image_save(
-                                    image.clone(),
-                                    slider_jpg_quality.value() as u8,
-                                    save_path,
-                                )?;
-                                *processed = None;
-                                btn_save_file.deactivate();
+                        if let Some(image) = &*processed {
+                            // the processed image is kept if saving fails so it can be saved again
+                            let format = export_format(
+                                &save_path,
+                                save_chooser.filter_value(),
+                                slider_jpg_quality.value() as u8,
+                            );
+                            let mut metadata = data_state.metadata.clone();
+                            if strip_gps.is_checked() {
+                                metadata.strip_gps();
+                            }
+                            match image_save_with_metadata(
+                                image,
+                                &metadata,
+                                format,
+                                save_path.clone(),
+                            ) {
+                                Ok(_) => {
+                                    *processed = None;
+                                    btn_save_file.deactivate();
+                                }
+                                Err(err) => show_error(
+                                    &format!("Could not save {}", save_path.display()),
+                                    &err,
+                                ),
+                            }
+                            app::redraw();
+                        }
+                    }
+                }
+                Message::LoadPreset => {
+                    preset_open_chooser.show();
+                    let preset_path = preset_open_chooser.filename();
+                    if preset_path.is_file() {
+                        match Preset::load(&preset_path) {
+                            Ok(mut preset) => {
+                                // a lut which can not be read is left out of the look
+                                // the path is kept as written, relative to the preset
+                                if let (Some(lut), Some(lut_path)) =
+                                    (&preset.lut, preset.lut_path())
+                                {
+                                    match Lut::load(&lut_path) {
+                                        Ok(table) => {
+                                            input_state.lut =
+                                                Some((lut.path.clone(), Arc::new(table)))
+                                        }
+                                        Err(err) => {
+                                            show_error(
+                                                &format!(
+                                                    "Could not load LUT {}",
+                                                    lut_path.display()
+                                                ),
+                                                &err,
+                                            );
+                                            preset.lut = None;
+                                        }
+                                    }
+                                }
+                                let film_stock = preset.film_stock.unwrap_or(FilmStockSettings {
+                                    stock: FilmStock::ALL
+                                        [choice_film_stock.value().max(0) as usize],
+                                    opacity: slider_film_stock_alpha.value() as f32,
+                                    blend_mode: blend_mode(&choice_film_stock_blend),
+                                });
+                                set_blend_choice(
+                                    &mut choice_film_stock_blend,
+                                    film_stock.blend_mode,
+                                );
+                                choice_film_stock.set_value(
+                                    FilmStock::ALL
+                                        .iter()
+                                        .position(|stock| *stock == film_stock.stock)
+                                        .unwrap_or_default()
+                                        as i32,
+                                );
+                                let lut_alpha = match &preset.lut {
+                                    Some(lut) => {
+                                        lut_name.set_label(&file_name(&lut.path));
+                                        choice_lut_interpolation.set_value(
+                                            match lut.interpolation {
+                                                LutInterpolation::Tetrahedral => 0,
+                                                LutInterpolation::Trilinear => 1,
+                                            },
+                                        );
+                                        set_blend_choice(&mut choice_lut_blend, lut.blend_mode);
+                                        lut.opacity as f64
+                                    }
+                                    None => slider_lut_alpha.value(),
+                                };
+                                set_effect_controls(
+                                    &mut lut_controls,
+                                    &mut lut_active,
+                                    [(&mut slider_lut_alpha, lut_alpha)],
+                                    preset.lut.is_some(),
+                                );
+                                let tone_curve_alpha = match &preset.tone_curve {
+                                    Some(tone_curve) => {
+                                        curves.borrow_mut().set(tone_curve);
+                                        set_blend_choice(
+                                            &mut choice_tone_curve_blend,
+                                            tone_curve.blend_mode,
+                                        );
+                                        tone_curve.opacity as f64
+                                    }
+                                    None => slider_tone_curve_alpha.value(),
+                                };
+                                set_effect_controls(
+                                    &mut tone_curve_controls,
+                                    &mut tone_curve_active,
+                                    [(&mut slider_tone_curve_alpha, tone_curve_alpha)],
+                                    preset.tone_curve.is_some(),
+                                );
+                                tone_curve_editor.redraw();
+                                let color_grade =
+                                    preset.color_grade.unwrap_or(ColorGradeSettings {
+                                        opacity: slider_grade_alpha.value() as f32,
+                                        balance: slider_grade_balance.value() as f32,
+                                        blend_mode: blend_mode(&choice_grade_blend),
+                                        shadows: Tint {
+                                            hue: slider_grade_shadow_hue.value() as f32,
+                                            saturation: slider_grade_shadow_sat.value() as f32,
+                                        },
+                                        midtones: Tint {
+                                            hue: slider_grade_mid_hue.value() as f32,
+                                            saturation: slider_grade_mid_sat.value() as f32,
+                                        },
+                                        highlights: Tint {
+                                            hue: slider_grade_high_hue.value() as f32,
+                                            saturation: slider_grade_high_sat.value() as f32,
+                                        },
+                                    });
+                                set_blend_choice(&mut choice_grade_blend, color_grade.blend_mode);
+                                set_effect_controls(
+                                    &mut color_grade_controls,
+                                    &mut color_grade_active,
+                                    [
+                                        (
+                                            &mut slider_grade_shadow_hue,
+                                            color_grade.shadows.hue.rem_euclid(360.0) as f64,
+                                        ),
+                                        (
+                                            &mut slider_grade_shadow_sat,
+                                            color_grade.shadows.saturation as f64,
+                                        ),
+                                        (
+                                            &mut slider_grade_mid_hue,
+                                            color_grade.midtones.hue.rem_euclid(360.0) as f64,
+                                        ),
+                                        (
+                                            &mut slider_grade_mid_sat,
+                                            color_grade.midtones.saturation as f64,
+                                        ),
+                                        (
+                                            &mut slider_grade_high_hue,
+                                            color_grade.highlights.hue.rem_euclid(360.0) as f64,
+                                        ),
+                                        (
+                                            &mut slider_grade_high_sat,
+                                            color_grade.highlights.saturation as f64,
+                                        ),
+                                        (&mut slider_grade_balance, color_grade.balance as f64),
+                                        (&mut slider_grade_alpha, color_grade.opacity as f64),
+                                    ],
+                                    preset.color_grade.is_some(),
+                                );
+                                set_effect_controls(
+                                    &mut film_stock_controls,
+                                    &mut film_stock_active,
+                                    [(&mut slider_film_stock_alpha, film_stock.opacity as f64)],
+                                    preset.film_stock.is_some(),
+                                );
+                                let vignette = preset.vignette.unwrap_or(VignetteSettings {
+                                    radius: slider_vignette_radius.value(),
+                                    opacity: slider_vignette_alpha.value() as f32,
+                                    center: input_state.vignette_center,
+                                    curve: VignetteCurve::Linear,
+                                    noise: true,
+                                    blend_mode: blend_mode(&choice_vignette_blend),
+                                    shape: VignetteShape::Circle,
+                                });
+                                set_blend_choice(&mut choice_vignette_blend, vignette.blend_mode);
+                                input_state.vignette_center = vignette.center;
+                                let (shape, roundness) = match vignette.shape {
+                                    VignetteShape::Circle => (0, None),
+                                    VignetteShape::Ellipse => (1, None),
+                                    VignetteShape::RoundedRect { roundness } => {
+                                        (2, Some(roundness as f64))
+                                    }
+                                };
+                                choice_vignette_shape.set_value(shape);
+                                choice_vignette_curve.set_value(match vignette.curve {
+                                    VignetteCurve::Linear => 0,
+                                    VignetteCurve::Smoothstep => 1,
+                                    VignetteCurve::Cos4 => 2,
+                                });
+                                set_roundness_control(&mut slider_vignette_roundness, roundness);
+                                set_effect_controls(
+                                    &mut vignette_controls,
+                                    &mut vignette_active,
+                                    [
+                                        (&mut slider_vignette_radius, vignette.radius),
+                                        (&mut slider_vignette_alpha, vignette.opacity as f64),
+                                    ],
+                                    preset.vignette.is_some(),
+                                );
+                                let filmgrain = preset.grain.unwrap_or(GrainSettings {
+                                    intensity: slider_filmgrain_intensity.value() as f32,
+                                    opacity: slider_filmgrain_alpha.value() as f32,
+                                    size: slider_filmgrain_size.value() as f32,
+                                    roughness: slider_filmgrain_roughness.value() as f32,
+                                    blend_mode: blend_mode(&choice_filmgrain_blend),
+                                    color: grain_color(
+                                        &choice_filmgrain_color,
+                                        &slider_filmgrain_saturation,
+                                        [
+                                            &slider_filmgrain_red,
+                                            &slider_filmgrain_green,
+                                            &slider_filmgrain_blue,
+                                        ],
+                                    ),
+                                });
+                                let chroma = match filmgrain.color {
+                                    GrainColor::Monochrome => None,
+                                    GrainColor::Chroma {
+                                        amplitude: [red, green, blue],
+                                        saturation,
+                                    } => Some(
+                                        [saturation, red, green, blue].map(|value| value as f64),
+                                    ),
+                                };
+                                choice_filmgrain_color.set_value(chroma.is_some() as i32);
+                                set_blend_choice(&mut choice_filmgrain_blend, filmgrain.blend_mode);
+                                set_chroma_controls(
+                                    [
+                                        &mut slider_filmgrain_saturation,
+                                        &mut slider_filmgrain_red,
+                                        &mut slider_filmgrain_green,
+                                        &mut slider_filmgrain_blue,
+                                    ],
+                                    chroma,
+                                );
+                                set_effect_controls(
+                                    &mut filmgrain_controls,
+                                    &mut filmgrain_active,
+                                    [
+                                        (
+                                            &mut slider_filmgrain_intensity,
+                                            filmgrain.intensity as f64,
+                                        ),
+                                        (&mut slider_filmgrain_alpha, filmgrain.opacity as f64),
+                                        (&mut slider_filmgrain_size, filmgrain.size as f64),
+                                        (
+                                            &mut slider_filmgrain_roughness,
+                                            filmgrain.roughness as f64,
+                                        ),
+                                    ],
+                                    preset.grain.is_some(),
+                                );
+                                let bleachbypass =
+                                    preset.bleach_bypass.unwrap_or(BleachBypassSettings {
+                                        blur: slider_bleachbypass_blur.value() as f32,
+                                        opacity: slider_bleachbypass_alpha.value() as f32,
+                                        contrast: slider_bleachbypass_contrast.value() as f32,
+                                        silver_retention: slider_bleachbypass_silver.value() as f32,
+                                        shadow_rolloff: slider_bleachbypass_shadows.value() as f32,
+                                        highlight_rolloff: slider_bleachbypass_highlights.value()
+                                            as f32,
+                                        blend_mode: blend_mode(&choice_bleachbypass_blend),
+                                    });
+                                set_blend_choice(
+                                    &mut choice_bleachbypass_blend,
+                                    bleachbypass.blend_mode,
+                                );
+                                set_effect_controls(
+                                    &mut bleachbypass_controls,
+                                    &mut bleachbypass_active,
+                                    [
+                                        (&mut slider_bleachbypass_blur, bleachbypass.blur as f64),
+                                        (
+                                            &mut slider_bleachbypass_alpha,
+                                            bleachbypass.opacity as f64,
+                                        ),
+                                        (
+                                            &mut slider_bleachbypass_contrast,
+                                            bleachbypass.contrast as f64,
+                                        ),
+                                        (
+                                            &mut slider_bleachbypass_silver,
+                                            bleachbypass.silver_retention as f64,
+                                        ),
+                                        (
+                                            &mut slider_bleachbypass_shadows,
+                                            bleachbypass.shadow_rolloff as f64,
+                                        ),
+                                        (
+                                            &mut slider_bleachbypass_highlights,
+                                            bleachbypass.highlight_rolloff as f64,
+                                        ),
+                                    ],
+                                    preset.bleach_bypass.is_some(),
+                                );
+                                affect_transparent.set_checked(preset.affect_transparent);
+                                input_state.preset = Preset {
+                                    seed: preset.seed.or(input_state.preset.seed),
+                                    ..preset
+                                };
+                                data_state.update_preview(&input_state, &mut preview_frame);
+                                app::redraw();
+                            }
+                            Err(err) => show_error(
+                                &format!("Could not load look {}", preset_path.display()),
+                                &err,
+                            ),
+                        }
+                    }
+                }
+                Message::SavePreset => {
+                    preset_save_chooser.show();
+                    let preset_path = preset_save_chooser.filename();
+                    if let Some(name) = preset_path.file_stem() {
+                        input_state.preset.name = name.to_string_lossy().to_string();
+                        let preset_path = preset_path.with_extension("toml");
+                        if let Err(err) = input_state.preset.save(&preset_path) {
+                            show_error(
+                                &format!("Could not save look {}", preset_path.display()),
+                                &err,
+                            );
+                        }
+                    }
+                }
+                Message::TransparencyToggle => {
+                    input_state.preset.affect_transparent = affect_transparent.is_checked();
+                    data_state.update_preview(&input_state, &mut preview_frame);
+                    app::redraw();
+                }
+                Message::PreviewClick(x, y) => {
+                    if let Some(center) = data_state.preview_position(x, y) {
+                        input_state.vignette_center = center;
+                        if input_state.preset.vignette.is_some() {
+                            input_state.set_vignette(
+                                &slider_vignette_radius,
+                                &slider_vignette_alpha,
+                                &choice_vignette_shape,
+                                &slider_vignette_roundness,
+                                &choice_vignette_curve,
+                                &choice_vignette_blend,
+                            );
+                            data_state.update_preview(&input_state, &mut preview_frame);
+                            app::redraw();
+                        }
+                    }
+                }
+                Message::FilmStockToggle => {
+                    if film_stock_controls.active() {
+                        film_stock_controls.deactivate();
+                        film_stock_active.set_checked(false);
+                        input_state.reset_film_stock();
+                    } else {
+                        film_stock_controls.activate();
+                        film_stock_active.set_checked(true);
+                        input_state.set_film_stock(
+                            &choice_film_stock,
+                            &slider_film_stock_alpha,
+                            &choice_film_stock_blend,
+                        );
+                    };
+                    data_state.update_preview(&input_state, &mut preview_frame);
+                    app::redraw();
+                }
+                Message::FilmStockChange => {
+                    input_state.set_film_stock(
+                        &choice_film_stock,
+                        &slider_film_stock_alpha,
+                        &choice_film_stock_blend,
+                    );
+                    data_state.update_preview(&input_state, &mut preview_frame);
+                    app::redraw();
+                }
+                Message::LutLoad => {
+                    lut_chooser.show();
+                    let lut_path = lut_chooser.filename();
+                    if lut_path.is_file() {
+                        // the lut is loaded once here and shared by every preview
+                        match Lut::load(&lut_path) {
+                            Ok(lut) => {
+                                lut_name.set_label(&file_name(&lut_path));
+                                input_state.lut = Some((lut_path, Arc::new(lut)));
+                                input_state.set_lut(
+                                    &choice_lut_interpolation,
+                                    &slider_lut_alpha,
+                                    &choice_lut_blend,
+                                );
+                                data_state.update_preview(&input_state, &mut preview_frame);
                                 app::redraw();
                             }
-                            None => (),
+                            Err(err) => show_error(
+                                &format!("Could not load LUT {}", lut_path.display()),
+                                &err,
+                            ),
                         }
                     }
                 }
+                Message::LutToggle => {
+                    if lut_controls.active() {
+                        lut_controls.deactivate();
+                        lut_active.set_checked(false);
+                        input_state.reset_lut();
+                    } else {
+                        lut_controls.activate();
+                        lut_active.set_checked(true);
+                        input_state.set_lut(
+                            &choice_lut_interpolation,
+                            &slider_lut_alpha,
+                            &choice_lut_blend,
+                        );
+                    };
+                    data_state.update_preview(&input_state, &mut preview_frame);
+                    app::redraw();
+                }
+                Message::LutChange => {
+                    input_state.set_lut(
+                        &choice_lut_interpolation,
+                        &slider_lut_alpha,
+                        &choice_lut_blend,
+                    );
+                    data_state.update_preview(&input_state, &mut preview_frame);
+                    app::redraw();
+                }
+                Message::ToneCurveToggle => {
+                    if tone_curve_controls.active() {
+                        tone_curve_controls.deactivate();
+                        tone_curve_active.set_checked(false);
+                        input_state.reset_tone_curve();
+                    } else {
+                        tone_curve_controls.activate();
+                        tone_curve_active.set_checked(true);
+                        input_state.set_tone_curve(
+                            &curves.borrow(),
+                            &slider_tone_curve_alpha,
+                            &choice_tone_curve_blend,
+                        );
+                    };
+                    tone_curve_editor.redraw();
+                    data_state.update_preview(&input_state, &mut preview_frame);
+                    app::redraw();
+                }
+                Message::ToneCurveChange => {
+                    input_state.set_tone_curve(
+                        &curves.borrow(),
+                        &slider_tone_curve_alpha,
+                        &choice_tone_curve_blend,
+                    );
+                    data_state.update_preview(&input_state, &mut preview_frame);
+                    app::redraw();
+                }
+                Message::ColorGradeToggle => {
+                    if color_grade_controls.active() {
+                        color_grade_controls.deactivate();
+                        color_grade_active.set_checked(false);
+                        input_state.reset_color_grade();
+                    } else {
+                        color_grade_controls.activate();
+                        color_grade_active.set_checked(true);
+                        input_state.set_color_grade(
+                            [
+                                &slider_grade_shadow_hue,
+                                &slider_grade_shadow_sat,
+                                &slider_grade_mid_hue,
+                                &slider_grade_mid_sat,
+                                &slider_grade_high_hue,
+                                &slider_grade_high_sat,
+                            ],
+                            &slider_grade_balance,
+                            &slider_grade_alpha,
+                            &choice_grade_blend,
+                        );
+                    };
+                    data_state.update_preview(&input_state, &mut preview_frame);
+                    app::redraw();
+                }
+                Message::ColorGradeChange => {
+                    input_state.set_color_grade(
+                        [
+                            &slider_grade_shadow_hue,
+                            &slider_grade_shadow_sat,
+                            &slider_grade_mid_hue,
+                            &slider_grade_mid_sat,
+                            &slider_grade_high_hue,
+                            &slider_grade_high_sat,
+                        ],
+                        &slider_grade_balance,
+                        &slider_grade_alpha,
+                        &choice_grade_blend,
+                    );
+                    data_state.update_preview(&input_state, &mut preview_frame);
+                    app::redraw();
+                }
                 Message::VignetteToggle => {
                     if vignette_controls.active() {
                         vignette_controls.deactivate();
@@ -346,18 +1495,32 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     } else {
                         vignette_controls.activate();
                         vignette_active.set_checked(true);
-                        input_state.set_vignette(&slider_vignette_radius, &slider_vignette_alpha);
+                        input_state.set_vignette(
+                            &slider_vignette_radius,
+                            &slider_vignette_alpha,
+                            &choice_vignette_shape,
+                            &slider_vignette_roundness,
+                            &choice_vignette_curve,
+                            &choice_vignette_blend,
+                        );
                     };
-                    data_state.reset_thumbnail();
-                    data_state.process_thumbnail(&input_state);
-                    data_state.set_fltk_image(&mut preview_frame)?;
+                    data_state.update_preview(&input_state, &mut preview_frame);
                     app::redraw();
                 }
                 Message::VignetteChange => {
-                    input_state.set_vignette(&slider_vignette_radius, &slider_vignette_alpha);
-                    data_state.reset_thumbnail();
-                    data_state.process_thumbnail(&input_state);
-                    data_state.set_fltk_image(&mut preview_frame)?;
+                    // the roundness only applies to the rounded rectangle
+                    let roundness = (choice_vignette_shape.value() == 2)
+                        .then(|| slider_vignette_roundness.value());
+                    set_roundness_control(&mut slider_vignette_roundness, roundness);
+                    input_state.set_vignette(
+                        &slider_vignette_radius,
+                        &slider_vignette_alpha,
+                        &choice_vignette_shape,
+                        &slider_vignette_roundness,
+                        &choice_vignette_curve,
+                        &choice_vignette_blend,
+                    );
+                    data_state.update_preview(&input_state, &mut preview_frame);
                     app::redraw();
                 }
                 Message::FilmgrainToggle => {
@@ -368,19 +1531,64 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     } else {
                         filmgrain_controls.activate();
                         filmgrain_active.set_checked(true);
-                        input_state
-                            .set_filmgrain(&slider_filmgrain_strength, &slider_filmgrain_alpha);
+                        input_state.set_filmgrain(
+                            &slider_filmgrain_intensity,
+                            &slider_filmgrain_alpha,
+                            &slider_filmgrain_size,
+                            &slider_filmgrain_roughness,
+                            grain_color(
+                                &choice_filmgrain_color,
+                                &slider_filmgrain_saturation,
+                                [
+                                    &slider_filmgrain_red,
+                                    &slider_filmgrain_green,
+                                    &slider_filmgrain_blue,
+                                ],
+                            ),
+                            &choice_filmgrain_blend,
+                        );
                     };
-                    data_state.reset_thumbnail();
-                    data_state.process_thumbnail(&input_state);
-                    data_state.set_fltk_image(&mut preview_frame)?;
+                    data_state.update_preview(&input_state, &mut preview_frame);
                     app::redraw();
                 }
                 Message::FilmgrainChange => {
-                    input_state.set_filmgrain(&slider_filmgrain_strength, &slider_filmgrain_alpha);
-                    data_state.reset_thumbnail();
-                    data_state.process_thumbnail(&input_state);
-                    data_state.set_fltk_image(&mut preview_frame)?;
+                    let color = grain_color(
+                        &choice_filmgrain_color,
+                        &slider_filmgrain_saturation,
+                        [
+                            &slider_filmgrain_red,
+                            &slider_filmgrain_green,
+                            &slider_filmgrain_blue,
+                        ],
+                    );
+                    // the saturation and the amplitudes only apply to chroma grain
+                    let chroma = (choice_filmgrain_color.value() == 1).then(|| {
+                        [
+                            &slider_filmgrain_saturation,
+                            &slider_filmgrain_red,
+                            &slider_filmgrain_green,
+                            &slider_filmgrain_blue,
+                        ]
+                        .map(|slider| slider.value())
+                    });
+                    set_chroma_controls(
+                        [
+                            &mut slider_filmgrain_saturation,
+                            &mut slider_filmgrain_red,
+                            &mut slider_filmgrain_green,
+                            &mut slider_filmgrain_blue,
+                        ],
+                        chroma,
+                    );
+                    input_state.set_filmgrain(
+                        &slider_filmgrain_intensity,
+                        &slider_filmgrain_alpha,
+                        &slider_filmgrain_size,
+                        &slider_filmgrain_roughness,
+                        color,
+                        &choice_filmgrain_blend,
+                    );
+                    data_state.update_preview(&input_state, &mut preview_frame);
                     app::redraw();
                 }
                 Message::BleachbypassToggle => {
@@ -394,19 +1602,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         input_state.set_bleachbypass(
                             &slider_bleachbypass_blur,
                             &slider_bleachbypass_alpha,
+                            [
+                                &slider_bleachbypass_contrast,
+                                &slider_bleachbypass_silver,
+                                &slider_bleachbypass_shadows,
+                                &slider_bleachbypass_highlights,
+                            ],
+                            &choice_bleachbypass_blend,
                         );
                     };
-                    data_state.reset_thumbnail();
-                    data_state.process_thumbnail(&input_state);
-                    data_state.set_fltk_image(&mut preview_frame)?;
+                    data_state.update_preview(&input_state, &mut preview_frame);
                     app::redraw();
                 }
                 Message::BleachbypassChange => {
-                    input_state
-                        .set_bleachbypass(&slider_bleachbypass_blur, &slider_bleachbypass_alpha);
-                    data_state.reset_thumbnail();
-                    data_state.process_thumbnail(&input_state);
-                    data_state.set_fltk_image(&mut preview_frame)?;
+                    input_state.set_bleachbypass(
+                        &slider_bleachbypass_blur,
+                        &slider_bleachbypass_alpha,
+                        [
+                            &slider_bleachbypass_contrast,
+                            &slider_bleachbypass_silver,
+                            &slider_bleachbypass_shadows,
+                            &slider_bleachbypass_highlights,
+                        ],
+                        &choice_bleachbypass_blend,
+                    );
+                    data_state.update_preview(&input_state, &mut preview_frame);
                     app::redraw();
                 }
             }
@@ -416,54 +1636,42 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn process_image(
-    image: &DynamicImage,
-    input_state: &InputState,
-    preview_size: u32,
-    preview: bool,
-) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
-    let (width, height) = image.dimensions();
-    let mut base_image = image.clone().to_rgb8();
-    if let Some(bleachbypass_input) = input_state.bleachbypass {
-        let overlay = bleach_bypass(&base_image, bleachbypass_input.0 as f32);
-        if let Some(overlay) = overlay {
-            palette_blend(
-                &mut base_image,
-                &overlay,
-                bleachbypass_input.1 as f32,
-                |c1, c2| c1.overlay(c2),
-            );
-        };
-    }
-    if let Some(vignette_input) = input_state.vignette {
-        let radius = if preview {
-            vignette_input.0
-        } else {
-            get_preview_scale(image, &preview_size) * vignette_input.0
-        };
-        let vignette = create_vignette(width, height, radius as u32, true);
-        palette_blend(
-            &mut base_image,
-            &vignette,
-            vignette_input.1 as f32,
-            |c1, c2| c1.multiply(c2),
-        );
-    }
-    if let Some(filmgrain_input) = input_state.filmgrain {
-        let filmgrain = film_grain(width, height, filmgrain_input.0 as u8);
-        palette_blend(
-            &mut base_image,
-            &filmgrain,
-            filmgrain_input.1 as f32,
-            |c1, c2| c1.multiply(c2),
-        );
+// the order of the filters matches the formats in export_format
+const EXPORT_FILTER: &str =
+    "JPEG\t*.{jpg,jpeg}\nPNG\t*.png\nTIFF\t*.{tif,tiff}\nTIFF 16-bit\t*.{tif,tiff}\nWebP\t*.webp";
+
+// an extension typed into the save dialog wins over the selected filter
+fn export_format(path: &Path, filter: i32, quality: u8) -> ExportFormat {
+    let selected = match filter {
+        1 => ExportFormat::Png,
+        2 => ExportFormat::Tiff,
+        3 => ExportFormat::Tiff16,
+        4 => ExportFormat::WebP,
+        _ => ExportFormat::Jpeg { quality },
+    };
+    match ExportFormat::from_path(path, quality) {
+        Some(ExportFormat::Tiff) if selected == ExportFormat::Tiff16 => ExportFormat::Tiff16,
+        Some(format) => format,
+        None => selected,
     }
+}
 
-    base_image
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
 }
 
-fn get_preview_scale(image_data: &DynamicImage, preview_size: &u32) -> f64 {
-    let (w, h) = image_data.dimensions();
-    let longer_axis = if w > h { w } else { h };
-    longer_axis as f64 / *preview_size as f64
+fn show_error(message: &str, err: &dyn std::fmt::Display) {
+    dialog::alert_default(&format!("{}:\n{}", message, err));
+}
+
+// the effect parameters are relative to the image size, so the thumbnail and the full image
+// are processed the same way
+fn process_image(
+    image: &DynamicImage,
+    input_state: &InputState,
+) -> Result<DynamicImage, retro_filter::Error> {
+    let context = Context::new(input_state.preset.seed.unwrap_or_default());
+    input_state.pipeline()?.process(image, &context)
 }
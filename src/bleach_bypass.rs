@@ -0,0 +1,181 @@
+use crate::{
+    color::{luminance, smoothstep},
+    BleachBypass, ColorPixel, Error, Result,
+};
+use image::{imageops::blur, ImageBuffer};
+use palette::{LinSrgb, Srgb};
+use rayon::prelude::*;
+
+// renders the silver layer of a bleach bypass: the luminance of the image with more contrast and
+// soft shoulders, mixed with the colors by the silver retention, the blur is relative to the
+// image diagonal
+// the rolloffs compress the shadows and the highlights, together they can be at most 1 so the
+// compressed ranges do not overlap
+pub fn bleach_bypass<P: ColorPixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    bleach_bypass: &BleachBypass,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    let BleachBypass {
+        blur: blur_amount,
+        contrast,
+        silver_retention,
+        shadow_rolloff,
+        highlight_rolloff,
+    } = *bleach_bypass;
+    if !(0.0..).contains(&blur_amount) {
+        return Err(Error::invalid_parameter(
+            "blur",
+            format!("{} is not a positive number", blur_amount),
+        ));
+    }
+    for (name, value) in [
+        ("contrast", contrast),
+        ("silver_retention", silver_retention),
+        ("shadow_rolloff", shadow_rolloff),
+        ("highlight_rolloff", highlight_rolloff),
+    ] {
+        if !(0.0..=1.0).contains(&value) {
+            return Err(Error::invalid_parameter(
+                name,
+                format!("{} is not between 0 and 1", value),
+            ));
+        }
+    }
+    if shadow_rolloff + highlight_rolloff > 1.0 {
+        return Err(Error::invalid_parameter(
+            "highlight_rolloff",
+            format!(
+                "{} and the shadow rolloff {} add up to more than 1",
+                highlight_rolloff, shadow_rolloff
+            ),
+        ));
+    }
+
+    let mut output = image.clone();
+    output.par_pixels_mut().for_each(|px| {
+        let [red, green, blue, alpha] = px.to_rgba_f32();
+        // the luminance is measured in linear light, the encoded luma would turn a blue sky
+        // almost black
+        let linear = Srgb::new(red, green, blue).into_linear();
+        let luminance = luminance([linear.red, linear.green, linear.blue]);
+        let gray = Srgb::from_linear(LinSrgb::new(luminance, luminance, luminance)).red;
+        let [red, green, blue] = [red, green, blue].map(|c| {
+            let silver = c + (gray - c) * silver_retention;
+            let value = silver + contrast * (smoothstep(silver) - silver);
+            rolloff(value, shadow_rolloff, highlight_rolloff)
+        });
+        *px = P::from_rgba_f32([red, green, blue, alpha]);
+    });
+    let (width, height) = image.dimensions();
+    let radius = blur_amount * (width as f32).hypot(height as f32);
+    if radius > 0.0 {
+        output = blur(&output, radius);
+    }
+    Ok(output)
+}
+
+// the shadows below the shadow rolloff and the highlights above one minus the highlight rolloff
+// are compressed exponentially, the slope stays 1 where the compression starts so there is no
+// visible edge
+fn rolloff(value: f32, shadow: f32, highlight: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+    let knee = 1.0 - highlight;
+    if highlight > 0.0 && value > knee {
+        knee + highlight * (1.0 - (-(value - knee) / highlight).exp())
+    } else if shadow > 0.0 && value < shadow {
+        shadow - shadow * (1.0 - (-(shadow - value) / shadow).exp())
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, Rgb32FImage};
+
+    fn silver(contrast: f32) -> BleachBypass {
+        BleachBypass {
+            blur: 0.0,
+            contrast,
+            silver_retention: 1.0,
+            shadow_rolloff: 0.0,
+            highlight_rolloff: 0.0,
+        }
+    }
+
+    #[test]
+    fn blue_stays_above_black() {
+        let image = Rgb32FImage::from_pixel(1, 1, Rgb([0.0, 0.0, 1.0]));
+        let output = bleach_bypass(&image, &silver(0.3)).unwrap();
+        let [red, green, blue] = output.get_pixel(0, 0).0;
+        assert!(red > 0.2, "{}", red);
+        assert!((red - green).abs() < 1e-6 && (red - blue).abs() < 1e-6);
+    }
+
+    #[test]
+    fn neutral_settings_keep_a_gray_ramp() {
+        let image = Rgb32FImage::from_fn(64, 1, |x, _| Rgb([x as f32 / 63.0; 3]));
+        let output = bleach_bypass(&image, &silver(0.0)).unwrap();
+        for (original, output) in image.pixels().zip(output.pixels()) {
+            for c in 0..3 {
+                assert!((original[c] - output[c]).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn rolloff_is_continuous_and_monotone() {
+        for (shadow, highlight) in [(0.3, 0.2), (0.5, 0.5), (0.0, 1.0), (1.0, 0.0)] {
+            let values: Vec<f32> = (0..=1000)
+                .map(|x| rolloff(x as f32 / 1000.0, shadow, highlight))
+                .collect();
+            for pair in values.windows(2) {
+                assert!(pair[1] >= pair[0] && pair[1] - pair[0] < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let image = Rgb32FImage::new(2, 2);
+        let invalid = [
+            (
+                "blur",
+                BleachBypass {
+                    blur: -0.1,
+                    ..silver(0.3)
+                },
+            ),
+            ("contrast", silver(1.5)),
+            (
+                "silver_retention",
+                BleachBypass {
+                    silver_retention: -0.1,
+                    ..silver(0.3)
+                },
+            ),
+            (
+                "shadow_rolloff",
+                BleachBypass {
+                    shadow_rolloff: 1.1,
+                    ..silver(0.3)
+                },
+            ),
+            (
+                "highlight_rolloff",
+                BleachBypass {
+                    shadow_rolloff: 0.6,
+                    highlight_rolloff: 0.5,
+                    ..silver(0.3)
+                },
+            ),
+        ];
+        for (name, settings) in invalid {
+            match bleach_bypass(&image, &settings) {
+                Err(Error::InvalidParameter { name: found, .. }) => assert_eq!(found, name),
+                result => panic!("{:?} gave {:?}", settings, result.map(|_| ())),
+            }
+        }
+    }
+}
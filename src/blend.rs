@@ -0,0 +1,383 @@
+use crate::{ColorPixel, Error, Mask, Result};
+use image::ImageBuffer;
+use palette::{Blend, LinSrgba, Srgb, WithAlpha};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// how the layer of an effect is combined with the image below it, the modes follow the w3c
+// compositing spec and work in linear light
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    SoftLight,
+    HardLight,
+    ColorDodge,
+    ColorBurn,
+    Darken,
+    Lighten,
+    Difference,
+    // the luminosity of the top layer with the hue and saturation of the base
+    Luminosity,
+    // the hue and saturation of the top layer with the luminosity of the base
+    Color,
+}
+impl BlendMode {
+    pub const ALL: [BlendMode; 13] = [
+        BlendMode::Normal,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::SoftLight,
+        BlendMode::HardLight,
+        BlendMode::ColorDodge,
+        BlendMode::ColorBurn,
+        BlendMode::Darken,
+        BlendMode::Lighten,
+        BlendMode::Difference,
+        BlendMode::Luminosity,
+        BlendMode::Color,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlendMode::Normal => "Normal",
+            BlendMode::Multiply => "Multiply",
+            BlendMode::Screen => "Screen",
+            BlendMode::Overlay => "Overlay",
+            BlendMode::SoftLight => "Soft light",
+            BlendMode::HardLight => "Hard light",
+            BlendMode::ColorDodge => "Color dodge",
+            BlendMode::ColorBurn => "Color burn",
+            BlendMode::Darken => "Darken",
+            BlendMode::Lighten => "Lighten",
+            BlendMode::Difference => "Difference",
+            BlendMode::Luminosity => "Luminosity",
+            BlendMode::Color => "Color",
+        }
+    }
+
+    // palette blends self as the source onto other as the backdrop
+    pub fn blend(&self, base: LinSrgba, top: LinSrgba) -> LinSrgba {
+        match self {
+            BlendMode::Normal => top.over(base),
+            BlendMode::Multiply => top.multiply(base),
+            BlendMode::Screen => top.screen(base),
+            BlendMode::Overlay => top.overlay(base),
+            BlendMode::SoftLight => composite(base, top, |base, top| {
+                std::array::from_fn(|c| soft_light(base[c], top[c]))
+            }),
+            BlendMode::HardLight => top.hard_light(base),
+            BlendMode::ColorDodge => top.dodge(base),
+            BlendMode::ColorBurn => top.burn(base),
+            BlendMode::Darken => top.darken(base),
+            BlendMode::Lighten => top.lighten(base),
+            BlendMode::Difference => top.difference(base),
+            BlendMode::Luminosity => composite(base, top, |base, top| set_lum(base, lum(top))),
+            BlendMode::Color => composite(base, top, |base, top| set_lum(top, lum(base))),
+        }
+    }
+}
+
+// composites the blended color of the modes palette does not have, the ones which mix the
+// channels, and of soft light, which palette gets wrong for dark backdrops
+fn composite<F>(base: LinSrgba, top: LinSrgba, blend_fn: F) -> LinSrgba
+where
+    F: Fn([f32; 3], [f32; 3]) -> [f32; 3],
+{
+    let backdrop = [base.red, base.green, base.blue];
+    let source = [top.red, top.green, top.blue];
+    let blended = blend_fn(backdrop, source);
+    let alpha = top.alpha + base.alpha * (1.0 - top.alpha);
+    if alpha <= 0.0 {
+        return LinSrgba::new(0.0, 0.0, 0.0, 0.0);
+    }
+    // the blended color only shows where the backdrop is opaque
+    let [red, green, blue] = std::array::from_fn(|c| {
+        let source = (1.0 - base.alpha) * source[c] + base.alpha * blended[c];
+        (top.alpha * source + (1.0 - top.alpha) * base.alpha * backdrop[c]) / alpha
+    });
+    LinSrgba::new(red, green, blue, alpha)
+}
+
+fn soft_light(backdrop: f32, source: f32) -> f32 {
+    if source <= 0.5 {
+        backdrop - (1.0 - 2.0 * source) * backdrop * (1.0 - backdrop)
+    } else {
+        let d = if backdrop <= 0.25 {
+            ((16.0 * backdrop - 12.0) * backdrop + 4.0) * backdrop
+        } else {
+            backdrop.sqrt()
+        };
+        backdrop + (2.0 * source - 1.0) * (d - backdrop)
+    }
+}
+
+fn lum([red, green, blue]: [f32; 3]) -> f32 {
+    0.3 * red + 0.59 * green + 0.11 * blue
+}
+
+// shifts the color to the given luminosity and pulls it back into range without changing it
+fn set_lum(color: [f32; 3], luminosity: f32) -> [f32; 3] {
+    let shift = luminosity - lum(color);
+    let color = color.map(|c| c + shift);
+    let l = lum(color);
+    let min = color.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = color.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    if min < 0.0 {
+        color.map(|c| l + (c - l) * l / (l - min))
+    } else if max > 1.0 {
+        color.map(|c| l + (c - l) * (1.0 - l) / (max - l))
+    } else {
+        color
+    }
+}
+
+// blends the top image onto the base image, the alpha of the base image is kept unless
+// affect_transparent is set, then the top image is also composited onto transparent areas
+pub fn palette_blend<P: ColorPixel>(
+    base_image: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    top_image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    alpha: f32,
+    affect_transparent: bool,
+    blend_mode: BlendMode,
+) -> Result<()> {
+    palette_blend_with(
+        base_image,
+        top_image,
+        alpha,
+        affect_transparent,
+        |base, top| blend_mode.blend(base, top),
+    )
+}
+
+// like palette_blend, but the alpha is multiplied per pixel with a mask of the same size, so
+// the effect only shows where the mask is bright
+pub fn palette_blend_masked<P: ColorPixel>(
+    base_image: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    top_image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    mask: &Mask,
+    alpha: f32,
+    affect_transparent: bool,
+    blend_mode: BlendMode,
+) -> Result<()> {
+    if base_image.dimensions() != mask.dimensions() {
+        return Err(Error::DimensionMismatch {
+            expected: base_image.dimensions(),
+            found: mask.dimensions(),
+        });
+    }
+    blend_images(
+        base_image,
+        top_image,
+        Some(mask),
+        alpha,
+        affect_transparent,
+        |base, top| blend_mode.blend(base, top),
+    )
+}
+
+// like palette_blend with a custom function which blends the top color onto the base color,
+// both in linear light
+pub fn palette_blend_with<P, F>(
+    base_image: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    top_image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    alpha: f32,
+    affect_transparent: bool,
+    blend_fn: F,
+) -> Result<()>
+where
+    P: ColorPixel,
+    F: Fn(LinSrgba, LinSrgba) -> LinSrgba + Sync,
+{
+    blend_images(
+        base_image,
+        top_image,
+        None,
+        alpha,
+        affect_transparent,
+        blend_fn,
+    )
+}
+
+fn blend_images<P, F>(
+    base_image: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    top_image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    mask: Option<&Mask>,
+    alpha: f32,
+    affect_transparent: bool,
+    blend_fn: F,
+) -> Result<()>
+where
+    P: ColorPixel,
+    F: Fn(LinSrgba, LinSrgba) -> LinSrgba + std::marker::Sync,
+{
+    if base_image.dimensions() != top_image.dimensions() {
+        return Err(Error::DimensionMismatch {
+            expected: base_image.dimensions(),
+            found: top_image.dimensions(),
+        });
+    }
+    if !(0.0..=1.0).contains(&alpha) {
+        return Err(Error::invalid_parameter(
+            "alpha",
+            format!("{} is not between 0 and 1", alpha),
+        ));
+    }
+
+    base_image
+        .par_pixels_mut()
+        .zip(top_image.par_pixels())
+        .enumerate()
+        .for_each(|(index, (c1, c2))| {
+            let [r1, g1, b1, a1] = c1.to_rgba_f32();
+            let [r2, g2, b2, a2] = c2.to_rgba_f32();
+            let color1 = Srgb::new(r1, g1, b1).into_linear();
+            let color1_alpha: LinSrgba = if affect_transparent {
+                color1.with_alpha(a1)
+            } else {
+                color1.opaque()
+            };
+            let coverage = mask.map_or(1.0, |mask| mask.as_raw()[index].clamp(0.0, 1.0));
+            let color2_alpha: LinSrgba = Srgb::new(r2, g2, b2)
+                .into_linear()
+                .with_alpha(alpha * coverage * a2);
+            let blended = blend_fn(color1_alpha, color2_alpha);
+            let color: Srgb = blended.color.into_encoding();
+            let blended_alpha = if affect_transparent {
+                blended.alpha
+            } else {
+                a1
+            };
+
+            *c1 = P::from_rgba_f32([color.red, color.green, color.blue, blended_alpha]);
+        });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: [f32; 5] = [0.0, 0.2, 0.5, 0.7, 1.0];
+
+    fn blend_gray(mode: BlendMode, base: f32, top: f32) -> f32 {
+        let base = LinSrgba::new(base, base, base, 1.0);
+        let top = LinSrgba::new(top, top, top, 1.0);
+        mode.blend(base, top).red
+    }
+
+    fn check<F: Fn(f32, f32) -> f32>(mode: BlendMode, formula: F) {
+        for base in VALUES {
+            for top in VALUES {
+                let blended = blend_gray(mode, base, top);
+                let expected = formula(base, top);
+                assert!(
+                    (blended - expected).abs() < 1e-5,
+                    "{:?} of {} onto {} is {}, expected {}",
+                    mode,
+                    top,
+                    base,
+                    blended,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn overlay_is_picked_by_the_base() {
+        check(BlendMode::Overlay, |base, top| {
+            if base <= 0.5 {
+                2.0 * base * top
+            } else {
+                1.0 - 2.0 * (1.0 - base) * (1.0 - top)
+            }
+        });
+    }
+
+    #[test]
+    fn hard_light_is_picked_by_the_top() {
+        check(BlendMode::HardLight, |base, top| {
+            if top <= 0.5 {
+                2.0 * base * top
+            } else {
+                1.0 - 2.0 * (1.0 - base) * (1.0 - top)
+            }
+        });
+    }
+
+    #[test]
+    fn hard_light_is_the_overlay_of_earlier_versions() {
+        // earlier versions blended with the operands of palette swapped
+        for base in VALUES {
+            for top in VALUES {
+                let base = LinSrgba::new(base, 0.3, 0.8, 1.0);
+                let top = LinSrgba::new(top, 0.6, 0.1, 1.0);
+                let legacy = base.overlay(top);
+                let blended = BlendMode::HardLight.blend(base, top);
+                assert!(
+                    (legacy.red - blended.red).abs() < 1e-5
+                        && (legacy.green - blended.green).abs() < 1e-5
+                        && (legacy.blue - blended.blue).abs() < 1e-5
+                        && (legacy.alpha - blended.alpha).abs() < 1e-5,
+                    "{:?} != {:?}",
+                    legacy,
+                    blended
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn separable_modes_follow_the_formulas() {
+        check(BlendMode::Normal, |_, top| top);
+        check(BlendMode::Multiply, |base, top| base * top);
+        check(BlendMode::Screen, |base, top| base + top - base * top);
+        check(BlendMode::Darken, f32::min);
+        check(BlendMode::Lighten, f32::max);
+        check(BlendMode::Difference, |base, top| (base - top).abs());
+        check(BlendMode::SoftLight, soft_light);
+    }
+
+    #[test]
+    fn soft_light_of_gray_keeps_the_base() {
+        for base in VALUES {
+            assert!((blend_gray(BlendMode::SoftLight, base, 0.5) - base).abs() < 1e-6);
+        }
+        assert!(blend_gray(BlendMode::SoftLight, 0.2, 0.8) > 0.2);
+        assert!(blend_gray(BlendMode::SoftLight, 0.2, 0.1) < 0.2);
+    }
+
+    #[test]
+    fn transparent_top_keeps_the_base() {
+        let base = LinSrgba::new(0.2, 0.4, 0.6, 1.0);
+        let top = LinSrgba::new(0.9, 0.1, 0.5, 0.0);
+        for mode in BlendMode::ALL {
+            let blended = mode.blend(base, top);
+            for (blended, base) in [
+                (blended.red, base.red),
+                (blended.green, base.green),
+                (blended.blue, base.blue),
+                (blended.alpha, base.alpha),
+            ] {
+                assert!((blended - base).abs() < 1e-6, "{:?}", mode);
+            }
+        }
+    }
+
+    #[test]
+    fn luminosity_and_color_swap_the_luminosity() {
+        let base = LinSrgba::new(0.2, 0.4, 0.6, 1.0);
+        let top = LinSrgba::new(0.7, 0.5, 0.3, 1.0);
+        let luminosity = BlendMode::Luminosity.blend(base, top);
+        let color = BlendMode::Color.blend(base, top);
+        let lum_of = |c: LinSrgba| lum([c.red, c.green, c.blue]);
+        assert!((lum_of(luminosity) - lum_of(top)).abs() < 1e-5);
+        assert!((lum_of(color) - lum_of(base)).abs() < 1e-5);
+    }
+}
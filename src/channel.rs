@@ -0,0 +1,79 @@
+use image::{Pixel, Primitive, Rgb, Rgba};
+
+// subpixel types the effects can work on, values are converted to floats between 0 and 1
+pub trait Channel: Primitive + Send + Sync + 'static {
+    fn to_f32(self) -> f32;
+    fn from_f32(value: f32) -> Self;
+}
+
+impl Channel for u8 {
+    fn to_f32(self) -> f32 {
+        self as f32 / u8::MAX as f32
+    }
+    fn from_f32(value: f32) -> Self {
+        (value.clamp(0.0, 1.0) * u8::MAX as f32).round() as u8
+    }
+}
+
+impl Channel for u16 {
+    fn to_f32(self) -> f32 {
+        self as f32 / u16::MAX as f32
+    }
+    fn from_f32(value: f32) -> Self {
+        (value.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16
+    }
+}
+
+// float images are not clamped so values outside of 0 to 1 survive the effects
+impl Channel for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+// pixels the effects can be applied to, rgb pixels are treated as opaque
+pub trait ColorPixel: Pixel<Subpixel: Channel> + Send + Sync + 'static {
+    fn to_rgba_f32(&self) -> [f32; 4];
+    fn from_rgba_f32(rgba: [f32; 4]) -> Self;
+}
+
+impl<T: Channel> ColorPixel for Rgb<T>
+where
+    Rgb<T>: Pixel<Subpixel = T>,
+{
+    fn to_rgba_f32(&self) -> [f32; 4] {
+        [self[0].to_f32(), self[1].to_f32(), self[2].to_f32(), 1.0]
+    }
+    fn from_rgba_f32(rgba: [f32; 4]) -> Self {
+        Rgb([
+            T::from_f32(rgba[0]),
+            T::from_f32(rgba[1]),
+            T::from_f32(rgba[2]),
+        ])
+    }
+}
+
+impl<T: Channel> ColorPixel for Rgba<T>
+where
+    Rgba<T>: Pixel<Subpixel = T>,
+{
+    fn to_rgba_f32(&self) -> [f32; 4] {
+        [
+            self[0].to_f32(),
+            self[1].to_f32(),
+            self[2].to_f32(),
+            self[3].to_f32(),
+        ]
+    }
+    fn from_rgba_f32(rgba: [f32; 4]) -> Self {
+        Rgba([
+            T::from_f32(rgba[0]),
+            T::from_f32(rgba[1]),
+            T::from_f32(rgba[2]),
+            T::from_f32(rgba[3]),
+        ])
+    }
+}
@@ -0,0 +1,12 @@
+// color helpers shared by the effects
+
+// rec. 709 weights, the relative luminance of a linear color or the luma of a gamma encoded one
+pub(crate) fn luminance([red, green, blue]: [f32; 3]) -> f32 {
+    0.2126 * red + 0.7152 * green + 0.0722 * blue
+}
+
+// s-curve from 0 to 1 with a flat start and end, the value is clamped to 0..1 first
+pub(crate) fn smoothstep(value: f32) -> f32 {
+    let x = value.clamp(0.0, 1.0);
+    x * x * (3.0 - 2.0 * x)
+}
@@ -0,0 +1,194 @@
+use crate::{
+    color::{luminance, smoothstep},
+    ColorGrade, ColorPixel, Error, Result,
+};
+use image::ImageBuffer;
+use palette::{FromColor, Hsv, LinSrgb, Srgb};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// a color a tonal range is tinted with, the hue is in degrees and the saturation from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Tint {
+    pub hue: f32,
+    pub saturation: f32,
+}
+
+// tints the shadows, the midtones and the highlights in linear light, the tints only shift the
+// color, the luminance of a gray pixel stays the same
+// the tonal ranges are split at the lightness of the pixel, a balance from -1 to 1 moves the
+// split between them, positive values give more room to the highlights
+pub fn color_grade<P: ColorPixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    color_grade: &ColorGrade,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    let ColorGrade {
+        shadows,
+        midtones,
+        highlights,
+        balance,
+    } = *color_grade;
+    for (name, tint) in [
+        ("shadows", shadows),
+        ("midtones", midtones),
+        ("highlights", highlights),
+    ] {
+        if !(0.0..=1.0).contains(&tint.saturation) {
+            return Err(Error::invalid_parameter(
+                name,
+                format!("saturation {} is not between 0 and 1", tint.saturation),
+            ));
+        }
+    }
+    if !(-1.0..=1.0).contains(&balance) {
+        return Err(Error::invalid_parameter(
+            "balance",
+            format!("{} is not between -1 and 1", balance),
+        ));
+    }
+
+    let shifts = [shadows, midtones, highlights].map(chroma_shift);
+    // the lightness the shadows turn into the highlights at
+    let pivot = 0.5 - 0.4 * balance;
+    let mut output = image.clone();
+    output.par_pixels_mut().for_each(|px| {
+        let [red, green, blue, alpha] = px.to_rgba_f32();
+        let linear = Srgb::new(red, green, blue).into_linear();
+        let linear = [linear.red, linear.green, linear.blue];
+        let luminance = luminance(linear);
+        let lightness = Srgb::from_linear(LinSrgb::new(luminance, luminance, luminance)).red;
+        // 0 in the blacks, 0.5 at the pivot and 1 in the whites
+        let tone = if lightness < pivot {
+            0.5 * lightness / pivot
+        } else {
+            0.5 + 0.5 * (lightness - pivot) / (1.0 - pivot)
+        }
+        .clamp(0.0, 1.0);
+        let shadow_weight = smoothstep(1.0 - 2.0 * tone);
+        let highlight_weight = smoothstep(2.0 * tone - 1.0);
+        let weights = [
+            shadow_weight,
+            1.0 - shadow_weight - highlight_weight,
+            highlight_weight,
+        ];
+        let [red, green, blue] = std::array::from_fn(|c| {
+            let gain: f32 = 1.0
+                + weights
+                    .iter()
+                    .zip(&shifts)
+                    .map(|(weight, shift)| weight * shift[c])
+                    .sum::<f32>();
+            (linear[c] * gain).max(0.0)
+        });
+        let encoded = Srgb::from_linear(LinSrgb::new(red, green, blue));
+        *px = P::from_rgba_f32([encoded.red, encoded.green, encoded.blue, alpha]);
+    });
+    Ok(output)
+}
+
+// how much a pixel is scaled per channel by a tint, the shift has no luminance so only the color
+// changes
+fn chroma_shift(tint: Tint) -> [f32; 3] {
+    let color: LinSrgb = Srgb::from_color(Hsv::new(tint.hue, 1.0, 1.0)).into_linear();
+    let color = [color.red, color.green, color.blue];
+    let luminance = luminance(color);
+    color.map(|c| (c - luminance) * tint.saturation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, Rgb32FImage};
+
+    fn teal_and_orange(balance: f32) -> ColorGrade {
+        ColorGrade {
+            shadows: Tint {
+                hue: 190.0,
+                saturation: 0.6,
+            },
+            midtones: Tint {
+                hue: 300.0,
+                saturation: 0.3,
+            },
+            highlights: Tint {
+                hue: 35.0,
+                saturation: 0.8,
+            },
+            balance,
+        }
+    }
+
+    fn gray_ramp() -> Rgb32FImage {
+        Rgb32FImage::from_fn(64, 1, |x, _| Rgb([x as f32 / 63.0; 3]))
+    }
+
+    fn linear_luminance(px: &Rgb<f32>) -> f32 {
+        let linear = Srgb::new(px[0], px[1], px[2]).into_linear();
+        luminance([linear.red, linear.green, linear.blue])
+    }
+
+    #[test]
+    fn keeps_the_luminance_of_gray() {
+        let image = gray_ramp();
+        for balance in [-1.0, 0.0, 0.5] {
+            let graded = color_grade(&image, &teal_and_orange(balance)).unwrap();
+            for (original, graded) in image.pixels().zip(graded.pixels()) {
+                assert!(
+                    (linear_luminance(original) - linear_luminance(graded)).abs() < 1e-4,
+                    "{:?} became {:?}",
+                    original,
+                    graded
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tints_the_tonal_ranges() {
+        let graded = color_grade(&gray_ramp(), &teal_and_orange(0.0)).unwrap();
+        // teal shadows have more blue than red, orange highlights more red than blue
+        let shadow = graded.get_pixel(12, 0);
+        assert!(shadow[2] > shadow[0]);
+        let highlight = graded.get_pixel(56, 0);
+        assert!(highlight[0] > highlight[2]);
+    }
+
+    #[test]
+    fn neutral_tints_keep_the_image() {
+        let image =
+            Rgb32FImage::from_fn(16, 16, |x, y| Rgb([x as f32 / 15.0, y as f32 / 15.0, 0.3]));
+        let neutral = ColorGrade {
+            shadows: Tint::default(),
+            midtones: Tint::default(),
+            highlights: Tint::default(),
+            balance: 0.3,
+        };
+        let graded = color_grade(&image, &neutral).unwrap();
+        for (original, graded) in image.pixels().zip(graded.pixels()) {
+            for c in 0..3 {
+                assert!((original[c] - graded[c]).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let mut grade = teal_and_orange(1.5);
+        assert!(matches!(
+            color_grade(&gray_ramp(), &grade),
+            Err(Error::InvalidParameter {
+                name: "balance",
+                ..
+            })
+        ));
+        grade.balance = 0.0;
+        grade.midtones.saturation = -0.1;
+        assert!(matches!(
+            color_grade(&gray_ramp(), &grade),
+            Err(Error::InvalidParameter {
+                name: "midtones",
+                ..
+            })
+        ));
+    }
+}
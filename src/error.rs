@@ -0,0 +1,67 @@
+use image::ImageError;
+use std::{fmt, io};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    // the images that are combined do not have the same size
+    DimensionMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    InvalidParameter {
+        name: &'static str,
+        reason: String,
+    },
+    Decode(ImageError),
+    Encode(ImageError),
+    Io(io::Error),
+    Preset(String),
+    Lut(String),
+}
+
+impl Error {
+    pub(crate) fn invalid_parameter(name: &'static str, reason: impl Into<String>) -> Self {
+        Error::InvalidParameter {
+            name,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DimensionMismatch { expected, found } => write!(
+                f,
+                "image dimensions do not match, expected {}x{} but found {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+            Error::InvalidParameter { name, reason } => {
+                write!(f, "invalid parameter '{}': {}", name, reason)
+            }
+            Error::Decode(err) => write!(f, "could not decode image: {}", err),
+            Error::Encode(err) => write!(f, "could not encode image: {}", err),
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Preset(reason) => write!(f, "invalid preset: {}", reason),
+            Error::Lut(reason) => write!(f, "invalid lut: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Decode(err) | Error::Encode(err) => Some(err),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,453 @@
+use crate::{
+    metadata::{insert_jpeg_xmp, insert_png_xmp, read_exif, write_exif, ExifEntry, ExifIfd},
+    Error, Metadata, Result,
+};
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    error::EncodingError,
+    metadata::Orientation,
+    DynamicImage, ImageDecoder, ImageEncoder, ImageError, ImageFormat, ImageReader,
+};
+use std::{
+    fs,
+    io::{Cursor, Seek, Write},
+    path::{Path, PathBuf},
+};
+use tiff::{
+    encoder::{
+        colortype::{ColorType, RGB16, RGB8, RGBA16, RGBA8},
+        DirectoryEncoder, TiffEncoder, TiffKindStandard, TiffValue,
+    },
+    tags::{Tag, Type},
+    Directory, TiffResult,
+};
+
+const XMP_TIFF_TAG: u16 = 700;
+// descriptive tags of the first exif ifd, the others describe the data of the original image
+const EXIF_IMAGE_TAGS: [u16; 8] = [
+    0x010E, // image description
+    0x010F, // make
+    0x0110, // model
+    0x0131, // software
+    0x0132, // date time
+    0x013B, // artist
+    0x013C, // host computer
+    0x8298, // copyright
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Jpeg { quality: u8 },
+    // png keeps 16 bit precision if the image has it, all formats but jpeg keep transparency
+    Png,
+    Tiff,
+    Tiff16,
+    // lossless webp
+    WebP,
+}
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Jpeg { .. } => "jpg",
+            ExportFormat::Png => "png",
+            ExportFormat::Tiff | ExportFormat::Tiff16 => "tif",
+            ExportFormat::WebP => "webp",
+        }
+    }
+    // tif is exported with 8 bit, jpg with the given quality
+    pub fn from_extension(extension: &str, quality: u8) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "jpg" | "jpeg" => Some(ExportFormat::Jpeg { quality }),
+            "png" => Some(ExportFormat::Png),
+            "tif" | "tiff" => Some(ExportFormat::Tiff),
+            "webp" => Some(ExportFormat::WebP),
+            _ => None,
+        }
+    }
+    pub fn from_path(path: &Path, quality: u8) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?, quality)
+    }
+    fn matches_extension(&self, path: &Path) -> bool {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        match (self, extension) {
+            (ExportFormat::Jpeg { quality }, Some(extension)) => {
+                Self::from_extension(extension, *quality) == Some(*self)
+            }
+            (ExportFormat::Tiff16, Some(extension)) => {
+                Self::from_extension(extension, 0) == Some(ExportFormat::Tiff)
+            }
+            (_, Some(extension)) => Self::from_extension(extension, 0) == Some(*self),
+            (_, None) => false,
+        }
+    }
+}
+impl Default for ExportFormat {
+    fn default() -> Self {
+        ExportFormat::Jpeg { quality: 75 }
+    }
+}
+
+pub fn image_open(image_path: PathBuf) -> Result<DynamicImage> {
+    Ok(image_open_with_metadata(image_path)?.0)
+}
+
+// metadata which can not be read is skipped instead of failing to open the image, the image is
+// rotated upright according to its exif orientation, which is then removed from the exif and xmp
+pub fn image_open_with_metadata(image_path: PathBuf) -> Result<(DynamicImage, Metadata)> {
+    let img = ImageReader::open(&image_path)?;
+    let is_tiff = img.format() == Some(ImageFormat::Tiff);
+    let mut decoder = img.into_decoder().map_err(Error::Decode)?;
+    let mut metadata = if is_tiff {
+        tiff_metadata(&fs::read(&image_path)?)
+    } else {
+        Metadata {
+            icc_profile: decoder.icc_profile().ok().flatten(),
+            exif: decoder.exif_metadata().ok().flatten(),
+            xmp: decoder.xmp_metadata().ok().flatten(),
+        }
+    };
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image_data = DynamicImage::from_decoder(decoder).map_err(Error::Decode)?;
+    image_data.apply_orientation(orientation);
+    metadata.remove_orientation();
+    Ok((image_data, metadata))
+}
+
+// the tiff decoder of image reads no exif and fails on the xmp with the default limits, so the
+// metadata is taken from the first ifd of the file, the exif data gets the descriptive tags and
+// the sub ifds
+fn tiff_metadata(tiff: &[u8]) -> Metadata {
+    let ifd = read_exif(tiff).unwrap_or_default();
+    let value = |tag: u16| {
+        let entry = ifd.entries.iter().find(|entry| entry.tag == tag);
+        entry.map(|entry| entry.value.clone())
+    };
+    let exif = ExifIfd {
+        entries: ifd
+            .entries
+            .iter()
+            .filter(|entry| EXIF_IMAGE_TAGS.contains(&entry.tag))
+            .cloned()
+            .collect(),
+        sub_ifds: ifd.sub_ifds.clone(),
+    };
+    Metadata {
+        icc_profile: value(Tag::IccProfile.to_u16()),
+        exif: (exif != ExifIfd::default()).then(|| write_exif(&exif)),
+        xmp: value(XMP_TIFF_TAG),
+    }
+}
+
+// saves the image in the given format, the extension of the path is changed if it does not
+// match the format, returns the path the image was written to
+pub fn image_save(
+    image_data: &DynamicImage,
+    format: ExportFormat,
+    path: PathBuf,
+) -> Result<PathBuf> {
+    image_save_with_metadata(image_data, &Metadata::default(), format, path)
+}
+
+pub fn image_save_with_metadata(
+    image_data: &DynamicImage,
+    metadata: &Metadata,
+    format: ExportFormat,
+    path: PathBuf,
+) -> Result<PathBuf> {
+    if let ExportFormat::Jpeg { quality } = format {
+        if !(1..=100).contains(&quality) {
+            return Err(Error::invalid_parameter(
+                "quality",
+                format!("{} is not between 1 and 100", quality),
+            ));
+        }
+    }
+    let save_path = if format.matches_extension(&path) {
+        path
+    } else {
+        path.with_extension(format.extension())
+    };
+    let color = image_data.color();
+    let high_bit_depth = color.bytes_per_pixel() > color.channel_count();
+    let image = match format {
+        // jpeg can not store transparency
+        ExportFormat::Jpeg { .. } => DynamicImage::ImageRgb8(image_data.to_rgb8()),
+        ExportFormat::Png => convert(image_data, high_bit_depth),
+        ExportFormat::Tiff | ExportFormat::WebP => convert(image_data, false),
+        ExportFormat::Tiff16 => convert(image_data, true),
+    };
+    let (width, height) = (image.width(), image.height());
+    let color_type = image.color().into();
+
+    let icc_profile = metadata.icc_profile.clone().unwrap_or_default();
+    let exif = metadata.exif.clone().unwrap_or_default();
+
+    let mut bytes = Cursor::new(Vec::new());
+    match format {
+        ExportFormat::Jpeg { quality } => {
+            let mut encoder = JpegEncoder::new_with_quality(&mut bytes, quality);
+            set_metadata(&mut encoder, icc_profile, exif)?;
+            encoder.write_image(image.as_bytes(), width, height, color_type)
+        }
+        ExportFormat::Png => {
+            let mut encoder = PngEncoder::new(&mut bytes);
+            set_metadata(&mut encoder, icc_profile, exif)?;
+            encoder.write_image(image.as_bytes(), width, height, color_type)
+        }
+        ExportFormat::Tiff | ExportFormat::Tiff16 => encode_tiff(&image, metadata, &mut bytes)
+            .map_err(|err| ImageError::Encoding(EncodingError::new(ImageFormat::Tiff.into(), err))),
+        // the webp encoder of image can not write xmp
+        ExportFormat::WebP => {
+            let mut encoder = image_webp::WebPEncoder::new(&mut bytes);
+            encoder.set_icc_profile(icc_profile);
+            encoder.set_exif_metadata(exif);
+            encoder.set_xmp_metadata(metadata.xmp.clone().unwrap_or_default());
+            let color = if image.color().has_alpha() {
+                image_webp::ColorType::Rgba8
+            } else {
+                image_webp::ColorType::Rgb8
+            };
+            encoder
+                .encode(image.as_bytes(), width, height, color)
+                .map_err(|err| {
+                    ImageError::Encoding(EncodingError::new(ImageFormat::WebP.into(), err))
+                })
+        }
+    }
+    .map_err(Error::Encode)?;
+
+    let mut bytes = bytes.into_inner();
+    if let Some(xmp) = &metadata.xmp {
+        match format {
+            ExportFormat::Jpeg { .. } => insert_jpeg_xmp(&mut bytes, xmp),
+            ExportFormat::Png => insert_png_xmp(&mut bytes, xmp),
+            _ => {}
+        }
+    }
+    fs::write(&save_path, bytes)?;
+    Ok(save_path)
+}
+
+// the tiff encoder of image can only write the icc profile, so the tiff is written here with
+// the exif and xmp data as well
+fn encode_tiff<W: Write + Seek>(
+    image: &DynamicImage,
+    metadata: &Metadata,
+    writer: W,
+) -> TiffResult<()> {
+    let (width, height) = (image.width(), image.height());
+    match image {
+        DynamicImage::ImageRgb8(image) => {
+            write_tiff::<RGB8, _>(writer, width, height, image.as_raw(), metadata)
+        }
+        DynamicImage::ImageRgba8(image) => {
+            write_tiff::<RGBA8, _>(writer, width, height, image.as_raw(), metadata)
+        }
+        DynamicImage::ImageRgb16(image) => {
+            write_tiff::<RGB16, _>(writer, width, height, image.as_raw(), metadata)
+        }
+        image => {
+            let image = image.to_rgba16();
+            write_tiff::<RGBA16, _>(writer, width, height, image.as_raw(), metadata)
+        }
+    }
+}
+
+fn write_tiff<C: ColorType, W: Write + Seek>(
+    writer: W,
+    width: u32,
+    height: u32,
+    data: &[C::Inner],
+    metadata: &Metadata,
+) -> TiffResult<()>
+where
+    [C::Inner]: TiffValue,
+{
+    let mut encoder = TiffEncoder::new(writer)?;
+    let exif = metadata
+        .exif
+        .as_deref()
+        .and_then(read_exif)
+        .unwrap_or_default();
+    // the exif and gps ifds are written first so the image can point to them
+    let sub_ifds = write_sub_ifds(&mut encoder, &exif)?;
+    let mut image = encoder.new_image::<C>(width, height)?;
+    let directory = image.encoder();
+    if let Some(icc_profile) = &metadata.icc_profile {
+        directory.write_tag(Tag::IccProfile, icc_profile.as_slice())?;
+    }
+    if let Some(xmp) = &metadata.xmp {
+        directory.write_tag(Tag::from_u16_exhaustive(XMP_TIFF_TAG), xmp.as_slice())?;
+    }
+    let entries = exif
+        .entries
+        .iter()
+        .filter(|entry| EXIF_IMAGE_TAGS.contains(&entry.tag));
+    write_exif_entries(directory, entries, &sub_ifds)?;
+    image.write_data(data)
+}
+
+// returns the tags and the offsets of the written ifds, empty ifds are left out
+fn write_sub_ifds<W: Write + Seek>(
+    encoder: &mut TiffEncoder<W>,
+    ifd: &ExifIfd,
+) -> TiffResult<Vec<(u16, u32)>> {
+    let mut offsets = Vec::new();
+    for (tag, sub_ifd) in &ifd.sub_ifds {
+        let sub_ifds = write_sub_ifds(encoder, sub_ifd)?;
+        if sub_ifd.entries.is_empty() && sub_ifds.is_empty() {
+            continue;
+        }
+        let mut directory = encoder.extra_directory()?;
+        write_exif_entries(&mut directory, sub_ifd.entries.iter(), &sub_ifds)?;
+        offsets.push((*tag, directory.finish_with_offsets()?.offset));
+    }
+    Ok(offsets)
+}
+
+fn write_exif_entries<'a, W: Write + Seek>(
+    directory: &mut DirectoryEncoder<'_, W, TiffKindStandard>,
+    entries: impl Iterator<Item = &'a ExifEntry>,
+    sub_ifds: &[(u16, u32)],
+) -> TiffResult<()> {
+    let mut written = Directory::empty();
+    for entry in entries {
+        if let Some(field_type) = Type::from_u16(entry.field_type) {
+            let value = directory.write_entry_bytes(field_type, &entry.value)?;
+            written.extend([(Tag::from_u16_exhaustive(entry.tag), value)]);
+        }
+    }
+    directory.extend_from(&written);
+    for (tag, offset) in sub_ifds {
+        directory.write_tag(Tag::from_u16_exhaustive(*tag), *offset)?;
+    }
+    Ok(())
+}
+
+// rgb or rgba with 8 or 16 bit, depending on whether the image has an alpha channel
+fn convert(image: &DynamicImage, sixteen_bit: bool) -> DynamicImage {
+    match (sixteen_bit, image.color().has_alpha()) {
+        (false, false) => DynamicImage::ImageRgb8(image.to_rgb8()),
+        (false, true) => DynamicImage::ImageRgba8(image.to_rgba8()),
+        (true, false) => DynamicImage::ImageRgb16(image.to_rgb16()),
+        (true, true) => DynamicImage::ImageRgba16(image.to_rgba16()),
+    }
+}
+
+fn set_metadata<E: ImageEncoder>(
+    encoder: &mut E,
+    icc_profile: Vec<u8>,
+    exif: Vec<u8>,
+) -> Result<()> {
+    if !icc_profile.is_empty() {
+        encoder
+            .set_icc_profile(icc_profile)
+            .map_err(|err| Error::Encode(ImageError::Unsupported(err)))?;
+    }
+    if !exif.is_empty() {
+        encoder
+            .set_exif_metadata(exif)
+            .map_err(|err| Error::Encode(ImageError::Unsupported(err)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{exif, MAKE};
+    use image::RgbImage;
+
+    #[test]
+    fn tiff_exif_round_trips() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(3, 2, image::Rgb([1, 2, 3])));
+        let metadata = Metadata {
+            icc_profile: Some(vec![7; 40]),
+            exif: Some(exif(false)),
+            xmp: None,
+        };
+        let path =
+            std::env::temp_dir().join(format!("retrofilter-round-trip-{}.tif", std::process::id()));
+        let path = image_save_with_metadata(&image, &metadata, ExportFormat::Tiff, path).unwrap();
+        let (_, opened) = image_open_with_metadata(path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(opened.icc_profile, metadata.icc_profile);
+        assert_eq!(
+            read_exif(&opened.exif.unwrap()),
+            read_exif(&metadata.exif.unwrap())
+        );
+    }
+
+    #[test]
+    fn applies_and_removes_the_orientation() {
+        let mut ifd = read_exif(&exif(true)).unwrap();
+        ifd.entries.push(ExifEntry {
+            tag: 0x0112,
+            field_type: 3,
+            value: 6u16.to_ne_bytes().to_vec(),
+        });
+        let metadata = Metadata {
+            exif: Some(write_exif(&ifd)),
+            xmp: Some(br#"<rdf:Description tiff:Orientation="6" tiff:Make="Canon"/>"#.to_vec()),
+            ..Metadata::default()
+        };
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 2, image::Rgb([1, 2, 3])));
+        let path = std::env::temp_dir().join(format!(
+            "retrofilter-orientation-{}.jpg",
+            std::process::id()
+        ));
+        let path =
+            image_save_with_metadata(&image, &metadata, ExportFormat::default(), path).unwrap();
+        let (opened, opened_metadata) = image_open_with_metadata(path.clone()).unwrap();
+        assert_eq!((opened.width(), opened.height()), (2, 4));
+        let path =
+            image_save_with_metadata(&opened, &opened_metadata, ExportFormat::default(), path)
+                .unwrap();
+        let mut decoder = ImageReader::open(&path).unwrap().into_decoder().unwrap();
+        let saved_exif = decoder.exif_metadata().unwrap().unwrap();
+        let saved_xmp = decoder.xmp_metadata().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let saved_ifd = read_exif(&saved_exif).unwrap();
+        assert!(saved_ifd.entries.iter().all(|entry| entry.tag != 0x0112));
+        assert_eq!(saved_ifd.entries[0].value, MAKE);
+        assert_eq!(
+            std::str::from_utf8(&saved_xmp).unwrap(),
+            r#"<rdf:Description tiff:Make="Canon"/>"#
+        );
+    }
+
+    #[test]
+    fn tiff_keeps_exif_and_xmp() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(5, 4, image::Rgb([10, 20, 30])));
+        let xmp = b"<x:xmpmeta>tiff</x:xmpmeta>".to_vec();
+        for (format, little_endian) in [(ExportFormat::Tiff, true), (ExportFormat::Tiff16, false)] {
+            let metadata = Metadata {
+                exif: Some(exif(little_endian)),
+                xmp: Some(xmp.clone()),
+                ..Metadata::default()
+            };
+            let path = std::env::temp_dir().join(format!(
+                "retrofilter-metadata-{}-{:?}.tif",
+                std::process::id(),
+                format
+            ));
+            let path = image_save_with_metadata(&image, &metadata, format, path).unwrap();
+            let bytes = fs::read(&path).unwrap();
+            let (opened, opened_metadata) = image_open_with_metadata(path.clone()).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!((opened.width(), opened.height()), (5, 4));
+            assert_eq!(opened_metadata.xmp, Some(xmp.clone()));
+            // a tiff file is read like exif data
+            let ifd = read_exif(&bytes).unwrap();
+            let make = ifd.entries.iter().find(|entry| entry.tag == 0x010F);
+            assert_eq!(make.unwrap().value, MAKE);
+            let sub_ifds: Vec<u16> = ifd.sub_ifds.iter().map(|(tag, _)| *tag).collect();
+            assert_eq!(sub_ifds, [0x8769, 0x8825]);
+            let (_, exif_ifd) = &ifd.sub_ifds[0];
+            let exposure_time: Vec<u8> = [1u32, 250].iter().flat_map(|v| v.to_ne_bytes()).collect();
+            assert_eq!(exif_ifd.entries[0].value, exposure_time);
+        }
+    }
+}
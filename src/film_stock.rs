@@ -0,0 +1,278 @@
+use crate::{
+    color::{luminance, smoothstep},
+    film_grain, palette_blend, BlendMode, ColorPixel, GrainColor, Result,
+};
+use image::{imageops::blur, ImageBuffer};
+use rand::Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// highlights above this luminance bleed into their surroundings
+const HALATION_THRESHOLD: f32 = 0.75;
+// spread of the halation relative to the image diagonal
+const HALATION_RADIUS: f32 = 0.004;
+// light scattered back from the film base exposes the red layer most
+const HALATION_TINT: [f32; 3] = [1.0, 0.3, 0.1];
+
+// looks inspired by well known film stocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilmStock {
+    // warm color negative with soft contrast and muted colors
+    Portra,
+    // high contrast black and white with coarse grain
+    TriX,
+    // saturated slide film with deep blacks
+    Velvia,
+    // cool slide film with clean colors
+    Ektachrome,
+}
+
+struct Profile {
+    // rows give the red, green and blue output from the input channels, each row adds up to 1 so
+    // black and white stay neutral
+    mix: [[f32; 3]; 3],
+    saturation: f32,
+    // color cast of the midtones per channel, it fades out towards black and white
+    tint: [f32; 3],
+    // strength of the s-curve, 0 keeps the tones
+    contrast: f32,
+    // lifted blacks and lowered whites
+    black: f32,
+    white: f32,
+    halation: f32,
+    grain_intensity: f32,
+    grain_size: f32,
+    grain_roughness: f32,
+    grain_color: GrainColor,
+}
+
+impl FilmStock {
+    pub const ALL: [FilmStock; 4] = [
+        FilmStock::Portra,
+        FilmStock::TriX,
+        FilmStock::Velvia,
+        FilmStock::Ektachrome,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilmStock::Portra => "Portra",
+            FilmStock::TriX => "Tri-X",
+            FilmStock::Velvia => "Velvia",
+            FilmStock::Ektachrome => "Ektachrome",
+        }
+    }
+
+    fn profile(&self) -> Profile {
+        match self {
+            FilmStock::Portra => Profile {
+                mix: [[1.02, 0.0, -0.02], [0.0, 1.0, 0.0], [-0.02, 0.0, 1.02]],
+                saturation: 0.85,
+                tint: [0.03, 0.0, -0.05],
+                contrast: 0.15,
+                black: 0.03,
+                white: 0.02,
+                halation: 0.15,
+                grain_intensity: 0.25,
+                grain_size: 0.0008,
+                grain_roughness: 0.4,
+                grain_color: GrainColor::Chroma {
+                    amplitude: GrainColor::DEFAULT_AMPLITUDE,
+                    saturation: 0.3,
+                },
+            },
+            // panchromatic film is a little more sensitive to red than the eye
+            FilmStock::TriX => Profile {
+                mix: [[0.35, 0.55, 0.1]; 3],
+                saturation: 1.0,
+                tint: [0.0; 3],
+                contrast: 0.6,
+                black: 0.01,
+                white: 0.0,
+                halation: 0.0,
+                grain_intensity: 0.7,
+                grain_size: 0.0015,
+                grain_roughness: 0.7,
+                grain_color: GrainColor::Monochrome,
+            },
+            FilmStock::Velvia => Profile {
+                mix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+                saturation: 1.4,
+                tint: [0.0, -0.015, 0.0],
+                contrast: 0.5,
+                black: 0.0,
+                white: 0.0,
+                halation: 0.1,
+                grain_intensity: 0.15,
+                grain_size: 0.0006,
+                grain_roughness: 0.5,
+                grain_color: GrainColor::Chroma {
+                    amplitude: GrainColor::DEFAULT_AMPLITUDE,
+                    saturation: 0.2,
+                },
+            },
+            FilmStock::Ektachrome => Profile {
+                mix: [[1.0, 0.0, 0.0], [0.0, 0.98, 0.02], [0.0, 0.02, 0.98]],
+                saturation: 1.1,
+                tint: [-0.03, 0.0, 0.04],
+                contrast: 0.35,
+                black: 0.01,
+                white: 0.0,
+                halation: 0.1,
+                grain_intensity: 0.2,
+                grain_size: 0.0007,
+                grain_roughness: 0.5,
+                grain_color: GrainColor::Chroma {
+                    amplitude: GrainColor::DEFAULT_AMPLITUDE,
+                    saturation: 0.3,
+                },
+            },
+        }
+    }
+}
+
+// renders the image on the given film stock: color response and tone curve, halation around
+// the highlights and the grain of the film
+pub fn film_emulation<P: ColorPixel, R: Rng>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    stock: FilmStock,
+    rng: &mut R,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    let profile = stock.profile();
+    let mut output = film_response(image, stock);
+    halation(image, &mut output, &profile)?;
+
+    film_grain(
+        &output,
+        profile.grain_intensity,
+        profile.grain_size,
+        profile.grain_roughness,
+        profile.grain_color,
+        rng,
+    )
+}
+
+// the highlights of the image glow red around them on top of the rendered image
+fn halation<P: ColorPixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    output: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    profile: &Profile,
+) -> Result<()> {
+    let (width, height) = image.dimensions();
+    if profile.halation > 0.0 && width > 0 && height > 0 {
+        let mut glow = ImageBuffer::<P, Vec<P::Subpixel>>::new(width, height);
+        glow.par_pixels_mut()
+            .zip(image.par_pixels())
+            .for_each(|(glow, px)| {
+                let [red, green, blue, _] = px.to_rgba_f32();
+                let highlight = ((luminance([red, green, blue]) - HALATION_THRESHOLD)
+                    / (1.0 - HALATION_THRESHOLD))
+                    .clamp(0.0, 1.0);
+                let [r, g, b] = HALATION_TINT.map(|tint| tint * highlight);
+                *glow = P::from_rgba_f32([r, g, b, 1.0]);
+            });
+        let radius = HALATION_RADIUS * (width as f32).hypot(height as f32);
+        glow = blur(&glow, radius.max(0.5));
+        palette_blend(output, &glow, profile.halation, false, BlendMode::Screen)?;
+    }
+    Ok(())
+}
+
+// only the color response and the tone curve of the film stock, every pixel on its own
+pub fn film_response<P: ColorPixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    stock: FilmStock,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let profile = stock.profile();
+    let mut output = image.clone();
+    output.par_pixels_mut().for_each(|px| {
+        let [red, green, blue, alpha] = px.to_rgba_f32();
+        let mixed = profile
+            .mix
+            .map(|row| row[0] * red + row[1] * green + row[2] * blue);
+        let luminance = luminance(mixed);
+        let color: [f32; 3] = std::array::from_fn(|c| {
+            let x = (luminance + (mixed[c] - luminance) * profile.saturation).clamp(0.0, 1.0);
+            tone(x + profile.tint[c] * 4.0 * x * (1.0 - x), &profile)
+        });
+        *px = P::from_rgba_f32([color[0], color[1], color[2], alpha]);
+    });
+    output
+}
+
+// s-curve around the midtones which then gets squeezed between the black and the white point
+fn tone(value: f32, profile: &Profile) -> f32 {
+    let x = value.clamp(0.0, 1.0);
+    let curved = x + profile.contrast * (smoothstep(x) - x);
+    profile.black + (1.0 - profile.black - profile.white) * curved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, Rgb32FImage};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn colors() -> Rgb32FImage {
+        Rgb32FImage::from_fn(16, 16, |x, y| {
+            Rgb([x as f32 / 15.0, y as f32 / 15.0, (x + y) as f32 / 30.0])
+        })
+    }
+
+    #[test]
+    fn tri_x_is_black_and_white() {
+        for px in film_response(&colors(), FilmStock::TriX).pixels() {
+            assert_eq!(px[0], px[1]);
+            assert_eq!(px[1], px[2]);
+        }
+    }
+
+    #[test]
+    fn black_and_white_go_to_the_black_and_white_point() {
+        let image = Rgb32FImage::from_fn(2, 1, |x, _| Rgb([x as f32; 3]));
+        for stock in FilmStock::ALL {
+            let profile = stock.profile();
+            let output = film_response(&image, stock);
+            assert_eq!(output, film_response(&image, stock));
+            for c in 0..3 {
+                assert!((output.get_pixel(0, 0)[c] - profile.black).abs() < 1e-6);
+                assert!((output.get_pixel(1, 0)[c] - (1.0 - profile.white)).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn halation_only_brightens() {
+        // a bright spot on a dark background
+        let image = Rgb32FImage::from_fn(32, 32, |x, y| {
+            let bright = (12..20).contains(&x) && (12..20).contains(&y);
+            Rgb([if bright { 0.95 } else { 0.1 }; 3])
+        });
+        for stock in FilmStock::ALL {
+            let response = film_response(&image, stock);
+            let mut output = response.clone();
+            halation(&image, &mut output, &stock.profile()).unwrap();
+            for (output, response) in output.pixels().zip(response.pixels()) {
+                for c in 0..3 {
+                    assert!(output[c] >= response[c] - 1e-6, "{:?}", stock);
+                }
+            }
+            if stock.profile().halation > 0.0 {
+                // the glow reaches the dark pixels next to the spot
+                assert!(output.get_pixel(11, 15)[0] > response.get_pixel(11, 15)[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_film() {
+        let image = colors();
+        for stock in FilmStock::ALL {
+            let render =
+                |seed| film_emulation(&image, stock, &mut StdRng::seed_from_u64(seed)).unwrap();
+            assert_eq!(render(7), render(7));
+            assert_ne!(render(7), render(8));
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use crate::{color::luminance, ColorPixel, Error, Result};
+use image::{
+    imageops::{blur, resize, FilterType},
+    ImageBuffer, Luma, Rgb,
+};
+use rand::prelude::*;
+use rand_distr::StandardNormal;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// standard deviation of the grain at full intensity
+const GRAIN_DEVIATION: f32 = 0.15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GrainColor {
+    // the same grain in all channels, like black and white film
+    #[default]
+    Monochrome,
+    // every channel gets its own grain, like the dye clouds of color negative film
+    // the amplitude scales the grain of the red, green and blue channel, a saturation of 0 gives
+    // the same grain in all channels and 1 independent grain per channel
+    Chroma {
+        amplitude: [f32; 3],
+        saturation: f32,
+    },
+}
+impl GrainColor {
+    // the blue sensitive layer of color film is the grainiest
+    pub const DEFAULT_AMPLITUDE: [f32; 3] = [0.8, 0.9, 1.0];
+    pub const DEFAULT_SATURATION: f32 = 0.5;
+}
+
+// gaussian grain on top of the image, the size of a grain is relative to the image height,
+// a roughness of 0 gives soft and 1 crisp grain, like film the grain is strongest in the
+// midtones
+pub fn film_grain<P: ColorPixel, R: Rng>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    intensity: f32,
+    size: f32,
+    roughness: f32,
+    color: GrainColor,
+    rng: &mut R,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    if !(0.0..=1.0).contains(&intensity) {
+        return Err(Error::invalid_parameter(
+            "intensity",
+            format!("{} is not between 0 and 1", intensity),
+        ));
+    }
+    if !(size > 0.0 && size <= 1.0) {
+        return Err(Error::invalid_parameter(
+            "size",
+            format!("{} is not between 0 and 1", size),
+        ));
+    }
+    if !(0.0..=1.0).contains(&roughness) {
+        return Err(Error::invalid_parameter(
+            "roughness",
+            format!("{} is not between 0 and 1", roughness),
+        ));
+    }
+    if let GrainColor::Chroma {
+        amplitude,
+        saturation,
+    } = color
+    {
+        if !amplitude.iter().all(|a| (0.0..=1.0).contains(a)) {
+            return Err(Error::invalid_parameter(
+                "amplitude",
+                format!("{:?} is not between 0 and 1", amplitude),
+            ));
+        }
+        if !(0.0..=1.0).contains(&saturation) {
+            return Err(Error::invalid_parameter(
+                "saturation",
+                format!("{} is not between 0 and 1", saturation),
+            ));
+        }
+    }
+    let (width, height) = image.dimensions();
+    let mut output = image.clone();
+    if width == 0 || height == 0 {
+        return Ok(output);
+    }
+    let noise = color_noise(width, height, size, roughness, color, rng);
+    let deviation = intensity * GRAIN_DEVIATION;
+    output
+        .par_pixels_mut()
+        .zip(noise.par_pixels())
+        .for_each(|(px, noise)| {
+            let [red, green, blue, alpha] = px.to_rgba_f32();
+            let grain = deviation * grain_response(luminance([red, green, blue]));
+            *px = P::from_rgba_f32([
+                red + grain * noise[0],
+                green + grain * noise[1],
+                blue + grain * noise[2],
+                alpha,
+            ]);
+        });
+    Ok(output)
+}
+
+// film reacts most in the midtones, shadows and highlights keep a little grain
+fn grain_response(luminance: f32) -> f32 {
+    let midtones = 4.0 * luminance * (1.0 - luminance);
+    0.2 + 0.8 * midtones.clamp(0.0, 1.0)
+}
+
+// grain of the red, green and blue channel
+fn color_noise<R: Rng>(
+    width: u32,
+    height: u32,
+    size: f32,
+    roughness: f32,
+    color: GrainColor,
+    rng: &mut R,
+) -> ImageBuffer<Rgb<f32>, Vec<f32>> {
+    let shared = grain_noise(width, height, size, roughness, rng);
+    let (amplitude, saturation) = match color {
+        GrainColor::Monochrome => {
+            return ImageBuffer::from_fn(width, height, |x, y| {
+                let [n] = shared.get_pixel(x, y).0;
+                Rgb([n, n, n])
+            })
+        }
+        GrainColor::Chroma {
+            amplitude,
+            saturation,
+        } => (amplitude, saturation),
+    };
+    let channels: Vec<_> = (0..3)
+        .map(|_| grain_noise(width, height, size, roughness, rng))
+        .collect();
+    // the mix of shared and independent grain keeps a standard deviation of 1
+    let shared_weight = 1.0 - saturation;
+    let norm = shared_weight.hypot(saturation);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let [n] = shared.get_pixel(x, y).0;
+        Rgb(std::array::from_fn(|c| {
+            let [own] = channels[c].get_pixel(x, y).0;
+            amplitude[c] * (shared_weight * n + saturation * own) / norm
+        }))
+    })
+}
+
+// the size of the sample grid, at most the image resolution, and how many grains a sample stands
+// for
+fn grain_grid(width: u32, height: u32, size: f32) -> (u32, u32, f32) {
+    let grains = (1.0 / size).round().max(1.0);
+    let grain_height = (grains as u32).min(height).max(1);
+    let averaging = grains / grain_height as f32;
+    let grain_width = ((width as f64 * grain_height as f64 / height as f64).round() as u32).max(1);
+    (grain_width, grain_height, averaging)
+}
+
+// gaussian noise with a standard deviation of 1, one sample per grain scaled to the image size
+// grains smaller than a pixel are rendered at the image resolution instead, with the lower
+// deviation they would average out to
+fn grain_noise<R: Rng>(
+    width: u32,
+    height: u32,
+    size: f32,
+    roughness: f32,
+    rng: &mut R,
+) -> ImageBuffer<Luma<f32>, Vec<f32>> {
+    let (grain_width, grain_height, averaging) = grain_grid(width, height, size);
+    let mut noise = ImageBuffer::from_fn(grain_width, grain_height, |_, _| {
+        Luma([rng.sample::<f32, _>(StandardNormal)])
+    });
+    // soft grain clumps neighbouring grains together, the deviation is restored afterwards
+    let softness = 1.0 - roughness;
+    if softness > 0.0 {
+        noise = filtered(&noise, |noise| blur(noise, softness));
+        let variance = noise.iter().map(|n| (n * n) as f64).sum::<f64>() / noise.len() as f64;
+        if variance > 0.0 {
+            let deviation = variance.sqrt() as f32;
+            noise.iter_mut().for_each(|n| *n /= deviation);
+        }
+    }
+    if averaging > 1.0 {
+        noise.iter_mut().for_each(|n| *n /= averaging);
+    }
+    if noise.dimensions() != (width, height) {
+        noise = filtered(&noise, |noise| {
+            resize(noise, width, height, FilterType::Triangle)
+        });
+    }
+    noise
+}
+
+// the image filters clamp float samples to 0..1, so the noise is moved into that range for them
+fn filtered(
+    noise: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    filter: impl FnOnce(&ImageBuffer<Luma<f32>, Vec<f32>>) -> ImageBuffer<Luma<f32>, Vec<f32>>,
+) -> ImageBuffer<Luma<f32>, Vec<f32>> {
+    const RANGE: f32 = 16.0;
+    let mut shifted = noise.clone();
+    shifted.iter_mut().for_each(|n| *n = 0.5 + *n / RANGE);
+    let mut output = filter(&shifted);
+    output.iter_mut().for_each(|n| *n = (*n - 0.5) * RANGE);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    #[test]
+    fn tiny_grain_is_capped_at_the_image_resolution() {
+        let (width, height, averaging) = grain_grid(32, 24, 0.00001);
+        assert_eq!((width, height), (32, 24));
+        assert!((averaging - 100_000.0 / 24.0).abs() < 1e-2);
+        // grains larger than a pixel are not capped
+        assert_eq!(grain_grid(4000, 3000, 0.001), (1333, 1000, 1.0));
+        assert_eq!(grain_grid(300, 200, 1.0), (2, 1, 1.0));
+
+        let image = RgbImage::from_pixel(32, 24, Rgb([128, 128, 128]));
+        let mut rng = StdRng::seed_from_u64(1);
+        let output = film_grain(&image, 0.5, 0.00001, 0.5, GrainColor::Monochrome, &mut rng);
+        assert_eq!(output.unwrap().dimensions(), image.dimensions());
+    }
+
+    #[test]
+    fn capped_grain_averages_out() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let noise = grain_noise(100, 100, 0.001, 1.0, &mut rng);
+        let variance = noise.iter().map(|n| n * n).sum::<f32>() / noise.len() as f32;
+        // ten grains per pixel give a tenth of the deviation
+        assert!((variance.sqrt() - 0.1).abs() < 0.01, "{}", variance.sqrt());
+    }
+
+    #[test]
+    fn filtered_grain_stays_centered() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for roughness in [0.0, 1.0] {
+            let noise = grain_noise(200, 150, 0.01, roughness, &mut rng);
+            let mean = noise.iter().sum::<f32>() / noise.len() as f32;
+            assert!(mean.abs() < 0.05, "{}", mean);
+            assert!(noise.iter().any(|&n| n < -0.5));
+        }
+    }
+
+    fn deviation(noise: &[f32]) -> f32 {
+        (noise.iter().map(|n| n * n).sum::<f32>() / noise.len() as f32).sqrt()
+    }
+
+    fn split_channels(noise: &ImageBuffer<Rgb<f32>, Vec<f32>>) -> [Vec<f32>; 3] {
+        std::array::from_fn(|c| noise.pixels().map(|px| px[c]).collect())
+    }
+
+    #[test]
+    fn unsaturated_chroma_grain_is_the_same_in_all_channels() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let color = GrainColor::Chroma {
+            amplitude: [1.0; 3],
+            saturation: 0.0,
+        };
+        let noise = color_noise(64, 48, 0.02, 0.5, color, &mut rng);
+        for px in noise.pixels() {
+            assert_eq!(px[0], px[1]);
+            assert_eq!(px[1], px[2]);
+        }
+
+        // the amplitudes only scale the shared grain
+        let color = GrainColor::Chroma {
+            amplitude: GrainColor::DEFAULT_AMPLITUDE,
+            saturation: 0.0,
+        };
+        let noise = color_noise(64, 48, 0.02, 0.5, color, &mut rng);
+        for px in noise.pixels() {
+            assert!((px[0] / 0.8 - px[2]).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn saturated_chroma_grain_differs_per_channel() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let amplitude = [0.5, 0.75, 1.0];
+        let color = GrainColor::Chroma {
+            amplitude,
+            saturation: 1.0,
+        };
+        let noise = color_noise(200, 150, 0.01, 1.0, color, &mut rng);
+        let channels = split_channels(&noise);
+        // every channel keeps the spread of its amplitude
+        let spread = deviation(&channels[2]);
+        for c in 0..2 {
+            let expected = spread * amplitude[c] / amplitude[2];
+            assert!((deviation(&channels[c]) - expected).abs() < 0.1 * expected);
+        }
+        // independent grain is not correlated between the channels
+        let correlation = channels[0]
+            .iter()
+            .zip(&channels[2])
+            .map(|(red, blue)| red * blue)
+            .sum::<f32>()
+            / channels[0].len() as f32
+            / (deviation(&channels[0]) * deviation(&channels[2]));
+        assert!(correlation.abs() < 0.1, "{}", correlation);
+
+        // half saturated grain is partly shared
+        let color = GrainColor::Chroma {
+            amplitude,
+            saturation: 0.5,
+        };
+        let noise = color_noise(200, 150, 0.01, 1.0, color, &mut rng);
+        let channels = split_channels(&noise);
+        assert!(channels[0]
+            .iter()
+            .zip(&channels[1])
+            .any(|(red, green)| (red / 0.5 - green / 0.75).abs() > 0.1));
+    }
+}
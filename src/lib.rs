@@ -1,58 +1,39 @@
-use image::{imageops::blur, ImageBuffer, Rgb};
+mod bleach_bypass;
+mod blend;
+mod channel;
+mod color;
+mod color_grade;
+mod error;
+mod filehandling;
+mod film_stock;
+mod grain;
+mod lut;
+mod mask;
+mod metadata;
+mod pipeline;
+mod preset;
+mod tone_curve;
 mod vignette;
-use palette::{LinSrgba, Pixel, Srgb, WithAlpha};
-use rand::prelude::*;
-use rayon::prelude::*;
-pub use vignette::create_vignette;
-
-pub fn palette_blend<F>(
-    base_image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
-    top_image: &ImageBuffer<Rgb<u8>, Vec<u8>>,
-    alpha: f32,
-    blend_fn: F,
-) where
-    F: Fn(LinSrgba, LinSrgba) -> LinSrgba + std::marker::Sync,
-{
-    let base: &mut [Srgb<u8>] = Pixel::from_raw_slice_mut(base_image);
-    let top: &[Srgb<u8>] = Pixel::from_raw_slice(top_image);
-
-    base.par_iter_mut().zip(top).for_each(|(c1, c2)| {
-        let color1_alpha: LinSrgba = c1.into_format().into_linear().opaque();
-        let color2_alpha: LinSrgba = c2.into_format().into_linear().with_alpha(alpha);
-        let blended = blend_fn(color1_alpha, color2_alpha);
-
-        *c1 = blended.color.into_encoding().into_format();
-    });
-}
-
-pub fn film_grain(width: u32, height: u32, noise_amount: u8) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
-    let mut buffer = ImageBuffer::from_fn(width, height, |_x, _y| Rgb([255u8, 255u8, 255u8]));
-    let mut rng = rand::thread_rng();
-    buffer.pixels_mut().for_each(|px| {
-        if rng.gen_range(0..100) < noise_amount {
-            let random = rng.gen_range(0..100);
-            (0..3).into_iter().for_each(|i| {
-                px[i] -= random;
-            });
-        }
-    });
-    buffer = blur(&buffer, 0.3);
-    buffer
-}
-
-pub fn bleach_bypass(
-    image: &ImageBuffer<Rgb<u8>, Vec<u8>>,
-    blur_amount: f32,
-) -> Option<ImageBuffer<Rgb<u8>, Vec<u8>>> {
-    let (w, h) = image.dimensions();
-    let mut overlay = image.clone().into_raw();
-    overlay.chunks_mut(3).into_iter().for_each(|px| {
-        px[1] = px[0];
-        px[2] = px[0];
-    });
-    let mut output: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_raw(w, h, overlay)?;
-    if blur_amount > 0.0 {
-        output = blur(&output, blur_amount);
-    }
-    Some(output)
-}
+pub use bleach_bypass::bleach_bypass;
+pub use blend::{palette_blend, palette_blend_masked, palette_blend_with, BlendMode};
+pub use channel::{Channel, ColorPixel};
+pub use color_grade::{color_grade, Tint};
+pub use error::{Error, Result};
+pub use filehandling::{
+    image_open, image_open_with_metadata, image_save, image_save_with_metadata, ExportFormat,
+};
+pub use film_stock::{film_emulation, film_response, FilmStock};
+pub use grain::{film_grain, GrainColor};
+pub use lut::{apply_lut, Lut, LutInterpolation};
+pub use mask::{linear_gradient_mask, radial_gradient_mask, Gradient, LayerMask, Mask};
+pub use metadata::Metadata;
+pub use pipeline::{
+    retro_pipeline, BleachBypass, ColorGrade, ColorLut, Context, Effect, FilmEmulation, FilmGrain,
+    Layer, Pipeline, ToneCurve, Vignette,
+};
+pub use preset::{
+    BleachBypassSettings, ColorGradeSettings, FilmStockSettings, GrainSettings, LutSettings,
+    Preset, ToneCurveSettings, VignetteSettings, PRESET_VERSION,
+};
+pub use tone_curve::{tone_curve, Spline};
+pub use vignette::{create_vignette, VignetteCurve, VignetteShape};
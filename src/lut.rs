@@ -0,0 +1,465 @@
+use crate::{image_open, ColorPixel, Error, Result};
+use image::{DynamicImage, ImageBuffer};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+const MAX_LUT_1D_SIZE: usize = 65536;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LutInterpolation {
+    Trilinear,
+    // interpolates between 4 instead of 8 entries, which keeps the neutral axis exact
+    #[default]
+    Tetrahedral,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LutKind {
+    // a curve per channel
+    OneDimensional,
+    // a color cube, red changes fastest, then green, then blue
+    ThreeDimensional,
+}
+
+// a color lookup table which maps rgb values between the domain min and max
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut {
+    kind: LutKind,
+    size: usize,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    table: Vec<[f32; 3]>,
+}
+
+impl Lut {
+    // the largest 3d lut which is accepted, 256 entries per axis are already 50 million values
+    pub const MAX_3D_SIZE: usize = 256;
+
+    // .cube files are read as adobe cube luts, everything else as a haldclut image
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let is_cube = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("cube"));
+        if is_cube {
+            Self::from_cube(&fs::read_to_string(path)?)
+        } else {
+            Self::from_hald(&image_open(path.to_path_buf())?)
+        }
+    }
+
+    // parses an adobe .cube file with a 1d or 3d table
+    pub fn from_cube(cube: &str) -> Result<Self> {
+        let mut kind = None;
+        let mut size = 0;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+        for (number, line) in cube.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let invalid = |reason: &str| Error::Lut(format!("line {}: {}", number + 1, reason));
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            match keyword {
+                "TITLE" => {}
+                "LUT_1D_SIZE" | "LUT_3D_SIZE" => {
+                    if kind.is_some() {
+                        return Err(invalid("the size is given more than once"));
+                    }
+                    let (lut_kind, max_size) = if keyword == "LUT_1D_SIZE" {
+                        (LutKind::OneDimensional, MAX_LUT_1D_SIZE)
+                    } else {
+                        (LutKind::ThreeDimensional, Self::MAX_3D_SIZE)
+                    };
+                    size = match (words.next().map(str::parse), words.next()) {
+                        (Some(Ok(size)), None) if (2..=max_size).contains(&size) => size,
+                        _ => return Err(invalid("invalid size")),
+                    };
+                    kind = Some(lut_kind);
+                }
+                "DOMAIN_MIN" => {
+                    domain_min = parse_values(words).ok_or_else(|| invalid("invalid domain"))?
+                }
+                "DOMAIN_MAX" => {
+                    domain_max = parse_values(words).ok_or_else(|| invalid("invalid domain"))?
+                }
+                // written by resolve instead of the domain
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                    let [min, max] = parse_values(words).ok_or_else(|| invalid("invalid range"))?;
+                    domain_min = [min; 3];
+                    domain_max = [max; 3];
+                }
+                // other keywords of the format are not needed, anything else has to be an entry
+                _ if is_keyword(keyword) => {}
+                _ => {
+                    let values = parse_values(line.split_whitespace())
+                        .ok_or_else(|| invalid("expected three numbers"))?;
+                    table.push(values);
+                }
+            }
+        }
+        let kind = kind.ok_or_else(|| Error::Lut("the size of the table is missing".into()))?;
+        let expected = match kind {
+            LutKind::OneDimensional => size,
+            LutKind::ThreeDimensional => size * size * size,
+        };
+        if table.len() != expected {
+            return Err(Error::Lut(format!(
+                "expected {} entries but found {}",
+                expected,
+                table.len()
+            )));
+        }
+        if (0..3).any(|c| domain_min[c] >= domain_max[c]) {
+            return Err(Error::Lut(format!(
+                "the domain min {:?} is not below the max {:?}",
+                domain_min, domain_max
+            )));
+        }
+        Ok(Self {
+            kind,
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    // a haldclut of level n is an image of n³ by n³ pixels holding a cube with n² entries per
+    // axis, the pixels are in the same order as in a 3d .cube file
+    pub fn from_hald(image: &DynamicImage) -> Result<Self> {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let level = (2..=16).find(|level| level * level * level == width);
+        let level = match level {
+            Some(level) if width == height => level,
+            _ => {
+                return Err(Error::Lut(format!(
+                    "a haldclut is a square with a cubic side length, but the image is {}x{}",
+                    width, height
+                )))
+            }
+        };
+        let table = image.to_rgb32f().pixels().map(|pixel| pixel.0).collect();
+        Ok(Self::from_lattice(level * level, table))
+    }
+
+    // a 3d lut from the entries of the cube, red changes fastest, then green, then blue
+    pub(crate) fn from_lattice(size: usize, table: Vec<[f32; 3]>) -> Self {
+        Self {
+            kind: LutKind::ThreeDimensional,
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            table,
+        }
+    }
+
+    // writes the lut as an adobe .cube file
+    pub fn to_cube(&self, title: &str) -> String {
+        let mut cube = String::new();
+        if !title.is_empty() {
+            cube += &format!("TITLE \"{}\"\n", title.replace('"', "'"));
+        }
+        cube += &match self.kind {
+            LutKind::OneDimensional => format!("LUT_1D_SIZE {}\n", self.size),
+            LutKind::ThreeDimensional => format!("LUT_3D_SIZE {}\n", self.size),
+        };
+        if self.domain_min != [0.0; 3] || self.domain_max != [1.0; 3] {
+            let [r, g, b] = self.domain_min;
+            cube += &format!("DOMAIN_MIN {} {} {}\n", r, g, b);
+            let [r, g, b] = self.domain_max;
+            cube += &format!("DOMAIN_MAX {} {} {}\n", r, g, b);
+        }
+        for [r, g, b] in &self.table {
+            cube += &format!("{:.6} {:.6} {:.6}\n", r, g, b);
+        }
+        cube
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, title: &str) -> Result<()> {
+        fs::write(path, self.to_cube(title))?;
+        Ok(())
+    }
+
+    pub fn lookup(&self, rgb: [f32; 3], interpolation: LutInterpolation) -> [f32; 3] {
+        let last = (self.size - 1) as f32;
+        let position: [f32; 3] = std::array::from_fn(|c| {
+            let value = (rgb[c] - self.domain_min[c]) / (self.domain_max[c] - self.domain_min[c]);
+            value.clamp(0.0, 1.0) * last
+        });
+        match self.kind {
+            LutKind::OneDimensional => std::array::from_fn(|c| {
+                let (index, fraction) = split(position[c], self.size);
+                let low = self.table[index][c];
+                let high = self.table[index + 1][c];
+                low + (high - low) * fraction
+            }),
+            LutKind::ThreeDimensional => match interpolation {
+                LutInterpolation::Trilinear => self.trilinear(position),
+                LutInterpolation::Tetrahedral => self.tetrahedral(position),
+            },
+        }
+    }
+
+    fn entry(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.table[r + self.size * (g + self.size * b)]
+    }
+
+    fn trilinear(&self, position: [f32; 3]) -> [f32; 3] {
+        let [(r, fr), (g, fg), (b, fb)] = position.map(|p| split(p, self.size));
+        let (r1, g1, b1) = (r + 1, g + 1, b + 1);
+        let lerp = |a: [f32; 3], b: [f32; 3], t: f32| -> [f32; 3] {
+            std::array::from_fn(|c| a[c] + (b[c] - a[c]) * t)
+        };
+        let c00 = lerp(self.entry(r, g, b), self.entry(r1, g, b), fr);
+        let c10 = lerp(self.entry(r, g1, b), self.entry(r1, g1, b), fr);
+        let c01 = lerp(self.entry(r, g, b1), self.entry(r1, g, b1), fr);
+        let c11 = lerp(self.entry(r, g1, b1), self.entry(r1, g1, b1), fr);
+        lerp(lerp(c00, c10, fg), lerp(c01, c11, fg), fb)
+    }
+
+    // the cube between the surrounding entries is split into six tetrahedra along its diagonal,
+    // the one containing the position is interpolated
+    fn tetrahedral(&self, position: [f32; 3]) -> [f32; 3] {
+        let [(r, fr), (g, fg), (b, fb)] = position.map(|p| split(p, self.size));
+        let (r1, g1, b1) = (r + 1, g + 1, b + 1);
+        let c000 = self.entry(r, g, b);
+        let c111 = self.entry(r1, g1, b1);
+        // the two corners between the first and the last one and the weights of the four
+        let (c1, c2, weights) = if fr > fg {
+            if fg > fb {
+                let weights = [1.0 - fr, fr - fg, fg - fb, fb];
+                (self.entry(r1, g, b), self.entry(r1, g1, b), weights)
+            } else if fr > fb {
+                let weights = [1.0 - fr, fr - fb, fb - fg, fg];
+                (self.entry(r1, g, b), self.entry(r1, g, b1), weights)
+            } else {
+                let weights = [1.0 - fb, fb - fr, fr - fg, fg];
+                (self.entry(r, g, b1), self.entry(r1, g, b1), weights)
+            }
+        } else if fb > fg {
+            let weights = [1.0 - fb, fb - fg, fg - fr, fr];
+            (self.entry(r, g, b1), self.entry(r, g1, b1), weights)
+        } else if fb > fr {
+            let weights = [1.0 - fg, fg - fb, fb - fr, fr];
+            (self.entry(r, g1, b), self.entry(r, g1, b1), weights)
+        } else {
+            let weights = [1.0 - fg, fg - fr, fr - fb, fb];
+            (self.entry(r, g1, b), self.entry(r1, g1, b), weights)
+        };
+        std::array::from_fn(|c| {
+            weights[0] * c000[c] + weights[1] * c1[c] + weights[2] * c2[c] + weights[3] * c111[c]
+        })
+    }
+}
+
+// index of the entry below the position and the fraction towards the next one, the last
+// position belongs to the last pair of entries
+fn split(position: f32, size: usize) -> (usize, f32) {
+    let index = (position.floor() as usize).min(size - 2);
+    (index, position - index as f32)
+}
+
+// keywords are uppercase identifiers like LUT_3D_SIZE
+fn is_keyword(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_uppercase())
+        && word
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+// rust also parses nan and inf, which are no valid entries
+fn parse_values<'a, const N: usize>(words: impl Iterator<Item = &'a str>) -> Option<[f32; N]> {
+    let values: Vec<f32> = words
+        .map(|word| word.parse().ok().filter(|value: &f32| value.is_finite()))
+        .collect::<Option<_>>()?;
+    values.try_into().ok()
+}
+
+// maps the colors of the image through the lut, the alpha channel is kept
+pub fn apply_lut<P: ColorPixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    lut: &Lut,
+    interpolation: LutInterpolation,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    let mut output = image.clone();
+    output.par_pixels_mut().for_each(|px| {
+        let [red, green, blue, alpha] = px.to_rgba_f32();
+        let [red, green, blue] = lut.lookup([red, green, blue], interpolation);
+        *px = P::from_rgba_f32([red, green, blue, alpha]);
+    });
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 3d table of size 2 in .cube order
+    const CUBE_3D: &str = "\
+# comment
+TITLE \"test\"
+LUT_3D_SIZE 2
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1
+";
+
+    fn identity(size: usize) -> Lut {
+        let last = (size - 1) as f32;
+        let table = (0..size * size * size)
+            .map(|index| {
+                let (r, g, b) = (index % size, index / size % size, index / (size * size));
+                [r as f32 / last, g as f32 / last, b as f32 / last]
+            })
+            .collect();
+        Lut::from_lattice(size, table)
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        assert!(
+            (0..3).all(|c| (a[c] - b[c]).abs() < 1e-5),
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn parses_a_3d_table() {
+        let lut = Lut::from_cube(CUBE_3D).unwrap();
+        assert_eq!(lut, identity(2));
+    }
+
+    #[test]
+    fn parses_a_1d_table() {
+        let lut = Lut::from_cube("LUT_1D_SIZE 3\n0 0 0\n0.25 0.5 0.75\n1 1 1\n").unwrap();
+        assert_eq!(lut.kind, LutKind::OneDimensional);
+        assert_close(
+            lut.lookup([0.5, 0.5, 0.5], LutInterpolation::Tetrahedral),
+            [0.25, 0.5, 0.75],
+        );
+        assert_close(
+            lut.lookup([0.25, 0.75, 1.0], LutInterpolation::Trilinear),
+            [0.125, 0.75, 1.0],
+        );
+    }
+
+    #[test]
+    fn reads_the_domain() {
+        let cube = CUBE_3D.replace(
+            "LUT_3D_SIZE 2",
+            "LUT_3D_SIZE 2\nDOMAIN_MIN 0 0 -1\nDOMAIN_MAX 2 1 1",
+        );
+        let lut = Lut::from_cube(&cube).unwrap();
+        assert_eq!(lut.domain_min, [0.0, 0.0, -1.0]);
+        assert_eq!(lut.domain_max, [2.0, 1.0, 1.0]);
+        assert_close(
+            lut.lookup([1.0, 0.5, 0.0], LutInterpolation::Trilinear),
+            [0.5, 0.5, 0.5],
+        );
+    }
+
+    #[test]
+    fn reads_the_input_range() {
+        let cube = CUBE_3D.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0 4");
+        let lut = Lut::from_cube(&cube).unwrap();
+        assert_eq!(lut.domain_min, [0.0; 3]);
+        assert_eq!(lut.domain_max, [4.0; 3]);
+        let cube = "LUT_1D_SIZE 2\nLUT_1D_INPUT_RANGE -1 1\n0 0 0\n1 1 1\n";
+        assert_eq!(Lut::from_cube(cube).unwrap().domain_min, [-1.0; 3]);
+    }
+
+    #[test]
+    fn rejects_invalid_tables() {
+        let invalid = [
+            CUBE_3D.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nLUT_3D_SIZE 2"),
+            CUBE_3D.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nLUT_1D_SIZE 8"),
+            CUBE_3D.replace("1 1 1\n", ""),
+            CUBE_3D.to_string() + "1 1 1\n",
+            CUBE_3D.replace("LUT_3D_SIZE 2\n", ""),
+            CUBE_3D.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 1"),
+            CUBE_3D.replace("1 1 1", "nan 1 1"),
+            CUBE_3D.replace("1 1 1", "inf 1 1"),
+            CUBE_3D.replace("1 1 1", "1 1"),
+            CUBE_3D.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nDOMAIN_MIN 1 1 1"),
+        ];
+        for cube in invalid {
+            assert!(Lut::from_cube(&cube).is_err(), "{}", cube);
+        }
+    }
+
+    #[test]
+    fn reports_the_line_of_an_invalid_entry() {
+        let err = Lut::from_cube(&CUBE_3D.replace("0 1 1", "nan 1 1")).unwrap_err();
+        assert!(err.to_string().contains("line 10"), "{}", err);
+    }
+
+    #[test]
+    fn round_trips_through_cube() {
+        let table = (0..27)
+            .map(|index| [index as f32 / 26.0, 0.5, 1.0 - index as f32 / 26.0])
+            .collect();
+        let mut lut = Lut::from_lattice(3, table);
+        lut.domain_max = [2.0; 3];
+        let parsed = Lut::from_cube(&lut.to_cube("round trip")).unwrap();
+        assert_eq!(parsed.kind, lut.kind);
+        assert_eq!(parsed.size, lut.size);
+        assert_eq!(parsed.domain_max, lut.domain_max);
+        for (a, b) in parsed.table.iter().zip(&lut.table) {
+            assert_close(*a, *b);
+        }
+    }
+
+    #[test]
+    fn identity_keeps_the_colors() {
+        let lut = identity(5);
+        for rgb in [
+            [0.0, 0.0, 0.0],
+            [0.3, 0.7, 0.1],
+            [1.0, 0.5, 0.25],
+            [1.0, 1.0, 1.0],
+        ] {
+            for interpolation in [LutInterpolation::Trilinear, LutInterpolation::Tetrahedral] {
+                assert_close(lut.lookup(rgb, interpolation), rgb);
+            }
+        }
+    }
+
+    #[test]
+    fn tetrahedral_is_exact_on_the_neutral_axis() {
+        // the grays are squared and every other entry is colored
+        let size = 5;
+        let last = (size - 1) as f32;
+        let table = (0..size * size * size)
+            .map(|index| {
+                let (r, g, b) = (index % size, index / size % size, index / (size * size));
+                if r == g && g == b {
+                    let value = (r as f32 / last).powi(2);
+                    [value; 3]
+                } else {
+                    [1.0, 0.0, 1.0]
+                }
+            })
+            .collect();
+        let lut = Lut::from_lattice(size, table);
+        for gray in [0.1, 0.3, 0.55, 0.8] {
+            let position = gray * last;
+            let (low, high) = (position.floor() / last, position.ceil() / last);
+            let fraction = position - position.floor();
+            let expected = low * low + (high * high - low * low) * fraction;
+            let rgb = lut.lookup([gray; 3], LutInterpolation::Tetrahedral);
+            assert_close(rgb, [expected; 3]);
+        }
+    }
+}
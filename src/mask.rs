@@ -0,0 +1,192 @@
+use crate::{Error, Result};
+use image::{imageops, ImageBuffer, Luma};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, sync::Arc};
+
+// grayscale opacity per pixel from 0 to 1, an effect blended with a mask only shows where the
+// mask is bright
+pub type Mask = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+// a gradient which is rendered at the size of the image it is applied to, so a preview gets
+// the same mask as the full image
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Gradient {
+    // from 1 at the start to 0 at the end, both relative to the image size
+    Linear {
+        start: (f32, f32),
+        end: (f32, f32),
+    },
+    // from 1 within the inner radius to 0 beyond the outer radius, the center is relative to
+    // the image size and the radii to the image diagonal
+    Radial {
+        center: (f32, f32),
+        inner_radius: f32,
+        outer_radius: f32,
+    },
+}
+impl Gradient {
+    pub fn render(&self, width: u32, height: u32) -> Result<Mask> {
+        match *self {
+            Gradient::Linear { start, end } => linear_gradient_mask(width, height, start, end),
+            Gradient::Radial {
+                center,
+                inner_radius,
+                outer_radius,
+            } => radial_gradient_mask(width, height, center, inner_radius, outer_radius),
+        }
+    }
+}
+
+// the mask of a layer in a pipeline, a gradient or a grayscale image like a painted or a
+// luminance mask
+// an image of another size is scaled to the image the layer is applied to, so it also fits the
+// preview
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayerMask {
+    Gradient(Gradient),
+    Image(Arc<Mask>),
+}
+impl LayerMask {
+    pub fn render(&self, width: u32, height: u32) -> Result<Cow<'_, Mask>> {
+        match self {
+            LayerMask::Gradient(gradient) => Ok(Cow::Owned(gradient.render(width, height)?)),
+            LayerMask::Image(mask) if mask.dimensions() == (width, height) => {
+                Ok(Cow::Borrowed(mask))
+            }
+            LayerMask::Image(mask) => {
+                if mask.width() == 0 || mask.height() == 0 {
+                    return Err(Error::invalid_parameter("mask", "the mask is empty"));
+                }
+                Ok(Cow::Owned(imageops::resize(
+                    mask.as_ref(),
+                    width,
+                    height,
+                    imageops::FilterType::Triangle,
+                )))
+            }
+        }
+    }
+}
+impl From<Gradient> for LayerMask {
+    fn from(gradient: Gradient) -> Self {
+        LayerMask::Gradient(gradient)
+    }
+}
+impl From<Arc<Mask>> for LayerMask {
+    fn from(mask: Arc<Mask>) -> Self {
+        LayerMask::Image(mask)
+    }
+}
+impl From<Mask> for LayerMask {
+    fn from(mask: Mask) -> Self {
+        LayerMask::Image(Arc::new(mask))
+    }
+}
+
+// swapping the start and the end inverts the mask
+pub fn linear_gradient_mask(
+    width: u32,
+    height: u32,
+    start: (f32, f32),
+    end: (f32, f32),
+) -> Result<Mask> {
+    // the direction is measured in pixels so diagonal gradients stay perpendicular to it
+    let (dx, dy) = (
+        (end.0 - start.0) * width as f32,
+        (end.1 - start.1) * height as f32,
+    );
+    let length_squared = dx * dx + dy * dy;
+    if length_squared == 0.0 || !length_squared.is_finite() {
+        return Err(Error::invalid_parameter(
+            "end",
+            format!("{:?} does not give a direction from {:?}", end, start),
+        ));
+    }
+    let (x0, y0) = (start.0 * width as f32, start.1 * height as f32);
+    Ok(render_mask(width, height, |x, y| {
+        let t = ((x - x0) * dx + (y - y0) * dy) / length_squared;
+        1.0 - t.clamp(0.0, 1.0)
+    }))
+}
+
+// an inner radius larger than the outer one inverts the mask
+pub fn radial_gradient_mask(
+    width: u32,
+    height: u32,
+    center: (f32, f32),
+    inner_radius: f32,
+    outer_radius: f32,
+) -> Result<Mask> {
+    for (name, radius) in [
+        ("inner_radius", inner_radius),
+        ("outer_radius", outer_radius),
+    ] {
+        if !(0.0..).contains(&radius) {
+            return Err(Error::invalid_parameter(
+                name,
+                format!("{} is not a positive number", radius),
+            ));
+        }
+    }
+    let diagonal = (width as f32).hypot(height as f32);
+    let (inner, outer) = (inner_radius * diagonal, outer_radius * diagonal);
+    let (cx, cy) = (center.0 * width as f32, center.1 * height as f32);
+    Ok(render_mask(width, height, |x, y| {
+        let distance = (x - cx).hypot(y - cy);
+        // equal radii give a hard edge
+        if inner == outer {
+            return if distance <= inner { 1.0 } else { 0.0 };
+        }
+        let t = (distance - inner) / (outer - inner);
+        1.0 - t.clamp(0.0, 1.0)
+    }))
+}
+
+// evaluates the gradient at the pixel centers
+fn render_mask<F>(width: u32, height: u32, gradient: F) -> Mask
+where
+    F: Fn(f32, f32) -> f32 + Sync,
+{
+    let mut mask = Mask::new(width, height);
+    mask.par_enumerate_pixels_mut().for_each(|(x, y, px)| {
+        *px = Luma([gradient(x as f32 + 0.5, y as f32 + 0.5)]);
+    });
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_masks_are_scaled_to_the_image() {
+        let mask = LayerMask::from(Mask::from_fn(4, 2, |x, _| Luma([(x / 2) as f32])));
+        assert!(matches!(mask.render(4, 2).unwrap(), Cow::Borrowed(_)));
+        let scaled = mask.render(8, 4).unwrap();
+        assert_eq!(scaled.dimensions(), (8, 4));
+        assert_eq!(scaled.get_pixel(0, 3)[0], 0.0);
+        assert_eq!(scaled.get_pixel(7, 0)[0], 1.0);
+    }
+
+    #[test]
+    fn rejects_empty_image_masks() {
+        let mask = LayerMask::from(Mask::new(0, 0));
+        assert!(matches!(
+            mask.render(4, 4),
+            Err(Error::InvalidParameter { name: "mask", .. })
+        ));
+    }
+
+    #[test]
+    fn gradients_are_rendered_at_the_image_size() {
+        let mask = LayerMask::from(Gradient::Linear {
+            start: (0.0, 0.0),
+            end: (1.0, 0.0),
+        });
+        let rendered = mask.render(10, 3).unwrap();
+        assert_eq!(rendered.dimensions(), (10, 3));
+        assert!(rendered.get_pixel(0, 0)[0] > rendered.get_pixel(9, 0)[0]);
+    }
+}
@@ -0,0 +1,599 @@
+use image::metadata::Orientation;
+
+// metadata of an image which is carried over from the input to the exported image
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub icc_profile: Option<Vec<u8>>,
+    // raw exif data, starting with the tiff header
+    pub exif: Option<Vec<u8>>,
+    // xmp packet without the header of the container format
+    pub xmp: Option<Vec<u8>>,
+}
+
+const EXIF_IFD_TAG: u16 = 0x8769;
+const GPS_IFD_TAG: u16 = 0x8825;
+const INTEROP_IFD_TAG: u16 = 0xA005;
+const ORIENTATION_TAG: u16 = 0x0112;
+const XMP_GPS_PREFIX: &str = "exif:GPS";
+const XMP_ORIENTATION: &str = "tiff:Orientation";
+const XMP_JPEG_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const XMP_PNG_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.icc_profile.is_none() && self.exif.is_none() && self.xmp.is_none()
+    }
+    // removes the gps location from the exif and xmp data, metadata which can not be read is
+    // removed completely to not leak the location
+    pub fn strip_gps(&mut self) {
+        if self.exif.as_mut().is_some_and(|exif| !strip_exif_gps(exif)) {
+            self.exif = None;
+        }
+        self.xmp = self
+            .xmp
+            .as_ref()
+            .and_then(|xmp| std::str::from_utf8(xmp).ok())
+            .map(|xmp| strip_xmp(xmp, XMP_GPS_PREFIX).into_bytes());
+    }
+    // removes the orientation from the exif and xmp data once it is applied to the image, exif
+    // which can not be read is reset to no rotation instead
+    pub(crate) fn remove_orientation(&mut self) {
+        if let Some(exif) = &mut self.exif {
+            match read_exif(exif) {
+                Some(mut ifd) if ifd.entries.iter().any(|entry| entry.tag == ORIENTATION_TAG) => {
+                    ifd.entries.retain(|entry| entry.tag != ORIENTATION_TAG);
+                    *exif = write_exif(&ifd);
+                }
+                Some(_) => {}
+                None => {
+                    let _ = Orientation::remove_from_exif_chunk(exif);
+                }
+            }
+        }
+        if let Some(xmp) = self.xmp.as_mut() {
+            if let Ok(text) = std::str::from_utf8(xmp) {
+                *xmp = strip_xmp(text, XMP_ORIENTATION).into_bytes();
+            }
+        }
+    }
+}
+
+// an ifd of exif data with its values in the byte order of this machine
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct ExifIfd {
+    pub entries: Vec<ExifEntry>,
+    // the exif, gps and interoperability ifds with the tag pointing to them
+    pub sub_ifds: Vec<(u16, ExifIfd)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ExifEntry {
+    pub tag: u16,
+    pub field_type: u16,
+    pub value: Vec<u8>,
+}
+
+struct Exif<'a> {
+    data: &'a mut [u8],
+    little_endian: bool,
+}
+impl Exif<'_> {
+    fn read_u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset.checked_add(2)?)?;
+        let bytes = [bytes[0], bytes[1]];
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+    fn read_u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset.checked_add(4)?)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+    fn write_u16(&mut self, offset: usize, value: u16) {
+        let bytes = if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        };
+        self.data[offset..offset + 2].copy_from_slice(&bytes);
+    }
+    fn fill_zero(&mut self, start: usize, length: usize) {
+        let end = start.saturating_add(length).min(self.data.len());
+        if start < end {
+            self.data[start..end].fill(0);
+        }
+    }
+    // entries which can not be read are skipped, sub ifds are followed two levels deep so
+    // broken data can not loop
+    fn read_ifd(&self, ifd: usize, depth: usize) -> Option<ExifIfd> {
+        let count = self.read_u16(ifd)? as usize;
+        let mut result = ExifIfd::default();
+        for entry in (0..count).map(|i| ifd + 2 + i * 12) {
+            let (Some(tag), Some(field_type), Some(count)) = (
+                self.read_u16(entry),
+                self.read_u16(entry + 2),
+                self.read_u32(entry + 4),
+            ) else {
+                break;
+            };
+            if [EXIF_IFD_TAG, GPS_IFD_TAG, INTEROP_IFD_TAG].contains(&tag) {
+                let sub_ifd = self
+                    .read_u32(entry + 8)
+                    .filter(|_| depth < 2)
+                    .and_then(|offset| self.read_ifd(offset as usize, depth + 1));
+                result
+                    .sub_ifds
+                    .extend(sub_ifd.map(|sub_ifd| (tag, sub_ifd)));
+                continue;
+            }
+            let Some((size, unit)) = type_size(field_type) else {
+                continue;
+            };
+            let length = (count as usize).saturating_mul(size);
+            // values of up to 4 bytes are stored in the entry itself
+            let offset = match length {
+                0..=4 => Some(entry + 8),
+                _ => self.read_u32(entry + 8).map(|offset| offset as usize),
+            };
+            let value =
+                offset.and_then(|offset| self.data.get(offset..offset.checked_add(length)?));
+            let Some(value) = value else {
+                continue;
+            };
+            let mut value = value.to_vec();
+            if self.little_endian != cfg!(target_endian = "little") {
+                value.chunks_mut(unit).for_each(<[u8]>::reverse);
+            }
+            result.entries.push(ExifEntry {
+                tag,
+                field_type,
+                value,
+            });
+        }
+        Some(result)
+    }
+    // removes the gps ifd from the first ifd and overwrites the gps values
+    fn strip_gps(&mut self) -> Option<()> {
+        let ifd = self.read_u32(4)? as usize;
+        let count = self.read_u16(ifd)? as usize;
+        let entries = ifd + 2;
+        let end = entries + count * 12 + 4;
+        if self.data.len() < end {
+            return None;
+        }
+        let index = match (0..count).find(|i| self.read_u16(entries + i * 12) == Some(GPS_IFD_TAG))
+        {
+            Some(index) => index,
+            None => return Some(()),
+        };
+        let gps_ifd = self.read_u32(entries + index * 12 + 8)? as usize;
+        self.clear_ifd(gps_ifd);
+
+        // the following entries and the offset of the next ifd move up by one entry
+        self.data
+            .copy_within(entries + (index + 1) * 12..end, entries + index * 12);
+        self.fill_zero(end - 12, 12);
+        self.write_u16(ifd, count as u16 - 1);
+        Some(())
+    }
+    fn clear_ifd(&mut self, ifd: usize) {
+        let count = match self.read_u16(ifd) {
+            Some(count) => count as usize,
+            None => return,
+        };
+        for entry in (0..count).map(|i| ifd + 2 + i * 12) {
+            let size = match self.read_u16(entry + 2).and_then(type_size) {
+                Some((size, _)) => size,
+                None => continue,
+            };
+            let length = (self.read_u32(entry + 4).unwrap_or(0) as usize).saturating_mul(size);
+            // values of up to 4 bytes are stored in the entry itself
+            if length > 4 {
+                if let Some(offset) = self.read_u32(entry + 8) {
+                    self.fill_zero(offset as usize, length);
+                }
+            }
+        }
+        self.fill_zero(ifd, 2 + count * 12 + 4);
+    }
+}
+
+// the size of a value of a field type and of the numbers it is made of, rationals are two
+// numbers
+fn type_size(field_type: u16) -> Option<(usize, usize)> {
+    match field_type {
+        1 | 2 | 6 | 7 => Some((1, 1)),
+        3 | 8 => Some((2, 2)),
+        4 | 9 | 11 | 13 => Some((4, 4)),
+        5 | 10 => Some((8, 4)),
+        12 => Some((8, 8)),
+        _ => None,
+    }
+}
+
+// whether the exif data is little endian, none if it does not start with a tiff header
+fn little_endian(exif: &[u8]) -> Option<bool> {
+    match exif.get(0..4)? {
+        [0x49, 0x49, 42, 0] => Some(true),
+        [0x4d, 0x4d, 0, 42] => Some(false),
+        _ => None,
+    }
+}
+
+// the first ifd of exif data or of a tiff file with the ifds it points to
+pub(crate) fn read_exif(exif: &[u8]) -> Option<ExifIfd> {
+    let mut data = exif.to_vec();
+    let exif = Exif {
+        little_endian: little_endian(exif)?,
+        data: &mut data,
+    };
+    exif.read_ifd(exif.read_u32(4)? as usize, 0)
+}
+
+// writes exif data in the byte order of this machine
+pub(crate) fn write_exif(ifd: &ExifIfd) -> Vec<u8> {
+    let mut data = match cfg!(target_endian = "little") {
+        true => b"II".to_vec(),
+        false => b"MM".to_vec(),
+    };
+    data.extend(42u16.to_ne_bytes());
+    data.extend(8u32.to_ne_bytes());
+    write_ifd(&mut data, ifd);
+    data
+}
+
+// the values which do not fit into an entry and the sub ifds follow the ifd, returns its offset
+fn write_ifd(data: &mut Vec<u8>, ifd: &ExifIfd) -> u32 {
+    // ifds and values start on a word boundary
+    if data.len() % 2 == 1 {
+        data.push(0);
+    }
+    let start = data.len();
+    let count = ifd.entries.len() + ifd.sub_ifds.len();
+    data.resize(start + 2 + count * 12 + 4, 0);
+    let mut fields = Vec::with_capacity(count);
+    for entry in &ifd.entries {
+        let size = type_size(entry.field_type).map_or(1, |(size, _)| size);
+        let mut value = [0; 4];
+        if entry.value.len() <= 4 {
+            value[..entry.value.len()].copy_from_slice(&entry.value);
+        } else {
+            if data.len() % 2 == 1 {
+                data.push(0);
+            }
+            value = (data.len() as u32).to_ne_bytes();
+            data.extend(&entry.value);
+        }
+        let count = (entry.value.len() / size) as u32;
+        fields.push((entry.tag, entry.field_type, count, value));
+    }
+    for (tag, sub_ifd) in &ifd.sub_ifds {
+        let offset = write_ifd(data, sub_ifd);
+        fields.push((*tag, 4, 1, offset.to_ne_bytes()));
+    }
+    // entries are sorted by their tag
+    fields.sort_by_key(|(tag, ..)| *tag);
+    data[start..start + 2].copy_from_slice(&(count as u16).to_ne_bytes());
+    for (index, (tag, field_type, count, value)) in fields.into_iter().enumerate() {
+        let entry = start + 2 + index * 12;
+        data[entry..entry + 2].copy_from_slice(&tag.to_ne_bytes());
+        data[entry + 2..entry + 4].copy_from_slice(&field_type.to_ne_bytes());
+        data[entry + 4..entry + 8].copy_from_slice(&count.to_ne_bytes());
+        data[entry + 8..entry + 12].copy_from_slice(&value);
+    }
+    start as u32
+}
+
+// returns false if the exif data could not be read
+fn strip_exif_gps(exif: &mut [u8]) -> bool {
+    let little_endian = match little_endian(exif) {
+        Some(little_endian) => little_endian,
+        None => return false,
+    };
+    Exif {
+        data: exif,
+        little_endian,
+    }
+    .strip_gps()
+    .is_some()
+}
+
+// removes the properties starting with the prefix, which are either attributes or elements of
+// the description
+fn strip_xmp(xmp: &str, prefix: &str) -> String {
+    let mut stripped = String::with_capacity(xmp.len());
+    let mut rest = xmp;
+    while let Some(start) = rest.find(prefix) {
+        let name_start = start + prefix.len();
+        let name_end = rest[name_start..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .map_or(rest.len(), |end| name_start + end);
+        let before = &rest[..start];
+        let element = before.ends_with('<');
+        let end = if element {
+            xmp_element_end(rest, start, &rest[start..name_end])
+        } else if before.ends_with(char::is_whitespace) {
+            xmp_attribute_end(rest, name_end)
+        } else {
+            None
+        };
+        match end {
+            Some(end) => {
+                stripped.push_str(if element {
+                    &before[..before.len() - 1]
+                } else {
+                    before.trim_end()
+                });
+                rest = &rest[end..];
+            }
+            None => {
+                stripped.push_str(&rest[..name_end]);
+                rest = &rest[name_end..];
+            }
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+fn xmp_element_end(xmp: &str, start: usize, name: &str) -> Option<usize> {
+    let tag_end = start + xmp[start..].find('>')? + 1;
+    if xmp[..tag_end].ends_with("/>") {
+        return Some(tag_end);
+    }
+    let closing = format!("</{}>", name);
+    Some(tag_end + xmp[tag_end..].find(&closing)? + closing.len())
+}
+
+fn xmp_attribute_end(xmp: &str, name_end: usize) -> Option<usize> {
+    let value = xmp[name_end..].trim_start().strip_prefix('=')?.trim_start();
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value_start = xmp.len() - value.len() + 1;
+    Some(value_start + xmp[value_start..].find(quote)? + 1)
+}
+
+// adds an app1 segment after the jfif segment, packets which do not fit into a single segment
+// are left out
+pub(crate) fn insert_jpeg_xmp(jpeg: &mut Vec<u8>, xmp: &[u8]) {
+    let length = match u16::try_from(2 + XMP_JPEG_HEADER.len() + xmp.len()) {
+        Ok(length) => length,
+        Err(_) => return,
+    };
+    let mut position = 2;
+    if jpeg.get(2..4) == Some(&[0xFF, 0xE0]) && jpeg.len() > 6 {
+        position += 2 + u16::from_be_bytes([jpeg[4], jpeg[5]]) as usize;
+    }
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&length.to_be_bytes());
+    segment.extend_from_slice(XMP_JPEG_HEADER);
+    segment.extend_from_slice(xmp);
+    jpeg.splice(position..position, segment);
+}
+
+// adds an uncompressed itxt chunk after the ihdr chunk
+pub(crate) fn insert_png_xmp(png: &mut Vec<u8>, xmp: &[u8]) {
+    let mut data = b"iTXt".to_vec();
+    data.extend_from_slice(XMP_PNG_KEYWORD);
+    // no compression and empty language and translated keyword
+    data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    data.extend_from_slice(xmp);
+    let length = match u32::try_from(data.len() - 4) {
+        Ok(length) => length,
+        Err(_) => return,
+    };
+    let mut chunk = length.to_be_bytes().to_vec();
+    chunk.extend_from_slice(&data);
+    chunk.extend_from_slice(&crc32fast::hash(&data).to_be_bytes());
+    // signature, then length, type, 13 bytes of data and crc of the ihdr chunk
+    let position = 8 + 4 + 4 + 13 + 4;
+    if png.len() < position {
+        return;
+    }
+    png.splice(position..position, chunk);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use image::{
+        codecs::{jpeg::JpegDecoder, png::PngDecoder},
+        ImageDecoder, ImageFormat, RgbImage,
+    };
+    use std::io::Cursor;
+
+    pub(crate) const MAKE: &[u8] = b"Canon\0";
+    const EXPOSURE_TIME_TAG: u16 = 0x829A;
+    const GPS_IFD_OFFSET: usize = 82;
+
+    // exif with the make in the first ifd, an exposure time in the exif ifd and a latitude in
+    // the gps ifd, all values which do not fit into an entry are stored after their ifd
+    pub(crate) fn exif(little_endian: bool) -> Vec<u8> {
+        let mut data = Vec::new();
+        let u16_bytes = |value: u16| match little_endian {
+            true => value.to_le_bytes(),
+            false => value.to_be_bytes(),
+        };
+        let u32_bytes = |value: u32| match little_endian {
+            true => value.to_le_bytes(),
+            false => value.to_be_bytes(),
+        };
+        let entry = |data: &mut Vec<u8>, tag: u16, field_type: u16, count: u32, value: [u8; 4]| {
+            data.extend(u16_bytes(tag));
+            data.extend(u16_bytes(field_type));
+            data.extend(u32_bytes(count));
+            data.extend(value);
+        };
+        data.extend(if little_endian { b"II" } else { b"MM" });
+        data.extend(u16_bytes(42));
+        data.extend(u32_bytes(8));
+        // first ifd at 8
+        data.extend(u16_bytes(3));
+        entry(&mut data, 0x010F, 2, 6, u32_bytes(50));
+        entry(&mut data, EXIF_IFD_TAG, 4, 1, u32_bytes(56));
+        entry(&mut data, GPS_IFD_TAG, 4, 1, u32_bytes(82));
+        data.extend(u32_bytes(0));
+        data.extend(MAKE);
+        // exif ifd at 56
+        data.extend(u16_bytes(1));
+        entry(&mut data, EXPOSURE_TIME_TAG, 5, 1, u32_bytes(74));
+        data.extend(u32_bytes(0));
+        data.extend(u32_bytes(1));
+        data.extend(u32_bytes(250));
+        // gps ifd at 82
+        assert_eq!(data.len(), GPS_IFD_OFFSET);
+        data.extend(u16_bytes(2));
+        entry(&mut data, 0x0001, 2, 2, *b"N\0\0\0");
+        entry(&mut data, 0x0002, 5, 3, u32_bytes(112));
+        data.extend(u32_bytes(0));
+        for value in [52, 1, 31, 1, 1234, 100] {
+            data.extend(u32_bytes(value));
+        }
+        data
+    }
+
+    fn entry(ifd: &ExifIfd, tag: u16) -> Option<&ExifEntry> {
+        ifd.entries.iter().find(|entry| entry.tag == tag)
+    }
+
+    fn sub_ifd(ifd: &ExifIfd, tag: u16) -> Option<&ExifIfd> {
+        ifd.sub_ifds
+            .iter()
+            .find(|(sub_tag, _)| *sub_tag == tag)
+            .map(|(_, sub_ifd)| sub_ifd)
+    }
+
+    fn native_u32(values: &[u32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn reads_exif_in_both_byte_orders() {
+        for little_endian in [true, false] {
+            let ifd = read_exif(&exif(little_endian)).unwrap();
+            assert_eq!(entry(&ifd, 0x010F).unwrap().value, MAKE);
+            let exif_ifd = sub_ifd(&ifd, EXIF_IFD_TAG).unwrap();
+            let exposure_time = entry(exif_ifd, EXPOSURE_TIME_TAG).unwrap();
+            assert_eq!(exposure_time.value, native_u32(&[1, 250]));
+            let gps_ifd = sub_ifd(&ifd, GPS_IFD_TAG).unwrap();
+            assert_eq!(entry(gps_ifd, 0x0001).unwrap().value, b"N\0");
+            assert_eq!(
+                entry(gps_ifd, 0x0002).unwrap().value,
+                native_u32(&[52, 1, 31, 1, 1234, 100])
+            );
+        }
+    }
+
+    #[test]
+    fn writes_exif_which_reads_the_same() {
+        for little_endian in [true, false] {
+            let ifd = read_exif(&exif(little_endian)).unwrap();
+            assert_eq!(read_exif(&write_exif(&ifd)).unwrap(), ifd);
+        }
+    }
+
+    #[test]
+    fn strips_the_gps_ifd_and_its_values() {
+        for little_endian in [true, false] {
+            let mut metadata = Metadata {
+                exif: Some(exif(little_endian)),
+                ..Metadata::default()
+            };
+            metadata.strip_gps();
+            let stripped = metadata.exif.unwrap();
+            assert_eq!(stripped.len(), exif(little_endian).len());
+            // the gps ifd and the latitude after it are overwritten
+            assert!(stripped[GPS_IFD_OFFSET..].iter().all(|byte| *byte == 0));
+            let ifd = read_exif(&stripped).unwrap();
+            assert!(sub_ifd(&ifd, GPS_IFD_TAG).is_none());
+            assert_eq!(entry(&ifd, 0x010F).unwrap().value, MAKE);
+            assert!(sub_ifd(&ifd, EXIF_IFD_TAG).is_some());
+        }
+    }
+
+    #[test]
+    fn drops_exif_which_can_not_be_read() {
+        let mut metadata = Metadata {
+            exif: Some(b"not exif".to_vec()),
+            ..Metadata::default()
+        };
+        metadata.strip_gps();
+        assert_eq!(metadata.exif, None);
+        // a first ifd outside of the data
+        let mut truncated = exif(true);
+        truncated[4..8].copy_from_slice(&1000u32.to_le_bytes());
+        metadata.exif = Some(truncated);
+        metadata.strip_gps();
+        assert_eq!(metadata.exif, None);
+    }
+
+    #[test]
+    fn strips_xmp_gps_attributes() {
+        let xmp = r#"<rdf:Description rdf:about="" exif:GPSLatitude="52,31.2N" exif:GPSLongitude='13,24.1E' tiff:Make="Canon"/>"#;
+        assert_eq!(
+            strip_xmp(xmp, XMP_GPS_PREFIX),
+            r#"<rdf:Description rdf:about="" tiff:Make="Canon"/>"#
+        );
+    }
+
+    #[test]
+    fn strips_xmp_gps_elements() {
+        let xmp = "<rdf:Description>\n <exif:GPSLatitude>52,31.2N</exif:GPSLatitude>\n \
+                   <exif:GPSAltitudeRef/>\n <exif:GPSTimeStamp>\n  <rdf:Seq/>\n \
+                   </exif:GPSTimeStamp>\n <tiff:Make>Canon</tiff:Make>\n</rdf:Description>";
+        let stripped = strip_xmp(xmp, XMP_GPS_PREFIX);
+        assert!(!stripped.contains("GPS"), "{}", stripped);
+        assert!(stripped.contains("<tiff:Make>Canon</tiff:Make>"));
+        assert!(stripped.ends_with("</rdf:Description>"));
+    }
+
+    #[test]
+    fn keeps_xmp_without_gps() {
+        let xmp = r#"<x:xmpmeta><rdf:Description exif:ExposureTime="1/250"/></x:xmpmeta>"#;
+        assert_eq!(strip_xmp(xmp, XMP_GPS_PREFIX), xmp);
+    }
+
+    fn encoded(format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        RgbImage::from_pixel(4, 3, image::Rgb([200, 100, 50]))
+            .write_to(&mut bytes, format)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn inserts_xmp_into_jpeg() {
+        let xmp = b"<x:xmpmeta>jpeg</x:xmpmeta>";
+        let mut jpeg = encoded(ImageFormat::Jpeg);
+        insert_jpeg_xmp(&mut jpeg, xmp);
+        let mut decoder = JpegDecoder::new(Cursor::new(&jpeg)).unwrap();
+        assert_eq!(decoder.xmp_metadata().unwrap().as_deref(), Some(&xmp[..]));
+        assert_eq!(decoder.dimensions(), (4, 3));
+        assert!(image::load_from_memory(&jpeg).is_ok());
+    }
+
+    #[test]
+    fn inserts_xmp_into_png() {
+        let xmp = b"<x:xmpmeta>png</x:xmpmeta>";
+        let mut png = encoded(ImageFormat::Png);
+        insert_png_xmp(&mut png, xmp);
+        let mut decoder = PngDecoder::new(Cursor::new(&png)).unwrap();
+        assert_eq!(decoder.xmp_metadata().unwrap().as_deref(), Some(&xmp[..]));
+        assert_eq!(
+            image::load_from_memory(&png)
+                .unwrap()
+                .to_rgb8()
+                .dimensions(),
+            (4, 3)
+        );
+    }
+}
@@ -0,0 +1,506 @@
+use crate::{
+    apply_lut, bleach_bypass, color_grade, create_vignette, film_emulation, film_grain,
+    film_response, palette_blend, palette_blend_masked, tone_curve, BlendMode, Error, FilmStock,
+    GrainColor, LayerMask, Lut, LutInterpolation, Result, Tint, VignetteCurve, VignetteShape,
+};
+use image::{DynamicImage, Rgba, Rgba32FImage};
+use rand::{rngs::StdRng, SeedableRng};
+use std::sync::Arc;
+
+// context an effect is rendered in
+// seed is used for all randomness, so the same seed always gives the same output
+// all effect parameters are relative to the image size, so a preview looks like a miniature of
+// the processed image without any scaling
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Context {
+    pub seed: u64,
+}
+impl Context {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+// an effect renders a layer for the given image which is then blended onto it
+// effects work on float images so no precision is lost between them
+pub trait Effect: Send + Sync {
+    fn apply(&self, image: &Rgba32FImage, context: &Context) -> Result<Rgba32FImage>;
+    // the part of the effect which only depends on the color of a pixel, without blur, noise
+    // or anything depending on its position, none if nothing is left
+    fn color_only(&self) -> Option<Box<dyn Effect>> {
+        None
+    }
+}
+
+// the blur is relative to the image diagonal, the other parameters go from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BleachBypass {
+    pub blur: f32,
+    // strength of the s-curve
+    pub contrast: f32,
+    // how much of the color is replaced by the luminance, 1 gives a gray layer
+    pub silver_retention: f32,
+    // share of the tonal range at either end which is compressed
+    pub shadow_rolloff: f32,
+    pub highlight_rolloff: f32,
+}
+impl Effect for BleachBypass {
+    fn apply(&self, image: &Rgba32FImage, _context: &Context) -> Result<Rgba32FImage> {
+        bleach_bypass(image, self)
+    }
+    fn color_only(&self) -> Option<Box<dyn Effect>> {
+        Some(Box::new(BleachBypass { blur: 0.0, ..*self }))
+    }
+}
+
+// the radius is relative to the image diagonal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vignette {
+    pub radius: f64,
+    pub shape: VignetteShape,
+    // focal point relative to the image size
+    pub center: (f32, f32),
+    pub curve: VignetteCurve,
+    pub noise: bool,
+}
+impl Effect for Vignette {
+    fn apply(&self, image: &Rgba32FImage, context: &Context) -> Result<Rgba32FImage> {
+        let (width, height) = image.dimensions();
+        create_vignette(width, height, self, context.seed)
+    }
+}
+
+// intensity and roughness go from 0 to 1, the size of a grain is relative to the image height
+// the grain is rendered at that size and then scaled to the image, so a preview shows the
+// averaged grain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilmGrain {
+    pub intensity: f32,
+    pub size: f32,
+    pub roughness: f32,
+    pub color: GrainColor,
+}
+impl Effect for FilmGrain {
+    fn apply(&self, image: &Rgba32FImage, context: &Context) -> Result<Rgba32FImage> {
+        let mut rng = StdRng::seed_from_u64(context.seed);
+        film_grain(
+            image,
+            self.intensity,
+            self.size,
+            self.roughness,
+            self.color,
+            &mut rng,
+        )
+    }
+}
+
+// tone curve, color response, halation and grain of a film stock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilmEmulation {
+    pub stock: FilmStock,
+}
+impl Effect for FilmEmulation {
+    fn apply(&self, image: &Rgba32FImage, context: &Context) -> Result<Rgba32FImage> {
+        let mut rng = StdRng::seed_from_u64(context.seed);
+        film_emulation(image, self.stock, &mut rng)
+    }
+    fn color_only(&self) -> Option<Box<dyn Effect>> {
+        Some(Box::new(FilmResponse { stock: self.stock }))
+    }
+}
+
+// the film stock without halation and grain
+struct FilmResponse {
+    stock: FilmStock,
+}
+impl Effect for FilmResponse {
+    fn apply(&self, image: &Rgba32FImage, _context: &Context) -> Result<Rgba32FImage> {
+        Ok(film_response(image, self.stock))
+    }
+    fn color_only(&self) -> Option<Box<dyn Effect>> {
+        Some(Box::new(FilmResponse { stock: self.stock }))
+    }
+}
+
+// maps the colors through a lookup table, the table is shared between copies of the effect
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorLut {
+    pub lut: Arc<Lut>,
+    pub interpolation: LutInterpolation,
+}
+impl Effect for ColorLut {
+    fn apply(&self, image: &Rgba32FImage, _context: &Context) -> Result<Rgba32FImage> {
+        apply_lut(image, &self.lut, self.interpolation)
+    }
+    fn color_only(&self) -> Option<Box<dyn Effect>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+// curves through control points from 0 to 1 for every channel and for all of them, the channel
+// curves are applied first
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneCurve {
+    pub master: Vec<(f32, f32)>,
+    pub red: Vec<(f32, f32)>,
+    pub green: Vec<(f32, f32)>,
+    pub blue: Vec<(f32, f32)>,
+}
+impl ToneCurve {
+    pub const IDENTITY: [(f32, f32); 2] = [(0.0, 0.0), (1.0, 1.0)];
+}
+impl Default for ToneCurve {
+    fn default() -> Self {
+        Self {
+            master: Self::IDENTITY.to_vec(),
+            red: Self::IDENTITY.to_vec(),
+            green: Self::IDENTITY.to_vec(),
+            blue: Self::IDENTITY.to_vec(),
+        }
+    }
+}
+impl Effect for ToneCurve {
+    fn apply(&self, image: &Rgba32FImage, _context: &Context) -> Result<Rgba32FImage> {
+        tone_curve(image, self)
+    }
+    fn color_only(&self) -> Option<Box<dyn Effect>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+// tints for the shadows, the midtones and the highlights, the balance from -1 to 1 moves the
+// split between the shadows and the highlights
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ColorGrade {
+    pub shadows: Tint,
+    pub midtones: Tint,
+    pub highlights: Tint,
+    pub balance: f32,
+}
+impl Effect for ColorGrade {
+    fn apply(&self, image: &Rgba32FImage, _context: &Context) -> Result<Rgba32FImage> {
+        color_grade(image, self)
+    }
+    fn color_only(&self) -> Option<Box<dyn Effect>> {
+        Some(Box::new(*self))
+    }
+}
+
+pub struct Layer {
+    pub effect: Box<dyn Effect>,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    // limits the effect to a part of the image
+    pub mask: Option<LayerMask>,
+}
+
+// ordered list of effects, each one blended onto the result of the previous ones
+// by default transparent areas stay transparent, with affect_transparent the effects are also
+// composited onto them
+#[derive(Default)]
+pub struct Pipeline {
+    layers: Vec<Layer>,
+    affect_transparent: bool,
+}
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with<E: Effect + 'static>(
+        mut self,
+        effect: E,
+        opacity: f32,
+        blend_mode: BlendMode,
+    ) -> Self {
+        self.push(effect, opacity, blend_mode);
+        self
+    }
+    pub fn push<E: Effect + 'static>(&mut self, effect: E, opacity: f32, blend_mode: BlendMode) {
+        self.layers.push(Layer {
+            effect: Box::new(effect),
+            opacity,
+            blend_mode,
+            mask: None,
+        });
+    }
+    pub fn with_masked<E: Effect + 'static, M: Into<LayerMask>>(
+        mut self,
+        effect: E,
+        opacity: f32,
+        blend_mode: BlendMode,
+        mask: M,
+    ) -> Self {
+        self.push_masked(effect, opacity, blend_mode, mask);
+        self
+    }
+    pub fn push_masked<E: Effect + 'static, M: Into<LayerMask>>(
+        &mut self,
+        effect: E,
+        opacity: f32,
+        blend_mode: BlendMode,
+        mask: M,
+    ) {
+        self.layers.push(Layer {
+            effect: Box::new(effect),
+            opacity,
+            blend_mode,
+            mask: Some(mask.into()),
+        });
+    }
+    pub fn affect_transparent(mut self, affect_transparent: bool) -> Self {
+        self.affect_transparent = affect_transparent;
+        self
+    }
+    pub fn set_affect_transparent(&mut self, affect_transparent: bool) {
+        self.affect_transparent = affect_transparent;
+    }
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+    // the layers which only change colors, spatial effects like the vignette and the grain and
+    // masked layers are left out
+    pub fn color_only(&self) -> Pipeline {
+        let layers = self
+            .layers
+            .iter()
+            .filter(|layer| layer.mask.is_none())
+            .filter_map(|layer| {
+                Some(Layer {
+                    effect: layer.effect.color_only()?,
+                    opacity: layer.opacity,
+                    blend_mode: layer.blend_mode,
+                    mask: None,
+                })
+            })
+            .collect();
+        Pipeline {
+            layers,
+            affect_transparent: self.affect_transparent,
+        }
+    }
+    // bakes the color only part of the pipeline into a 3d lut with the given number of entries
+    // per axis
+    pub fn to_lut(&self, size: usize) -> Result<Lut> {
+        if !(2..=Lut::MAX_3D_SIZE).contains(&size) {
+            return Err(Error::invalid_parameter(
+                "size",
+                format!("{} is not between 2 and {}", size, Lut::MAX_3D_SIZE),
+            ));
+        }
+        // red changes along the rows, green and then blue from row to row
+        let last = (size - 1) as f32;
+        let mut lattice = Rgba32FImage::from_fn(size as u32, (size * size) as u32, |x, y| {
+            let (green, blue) = (y as usize % size, y as usize / size);
+            Rgba([
+                x as f32 / last,
+                green as f32 / last,
+                blue as f32 / last,
+                1.0,
+            ])
+        });
+        self.color_only().apply(&mut lattice, &Context::default())?;
+        Ok(Lut::from_lattice(
+            size,
+            lattice.pixels().map(|px| [px[0], px[1], px[2]]).collect(),
+        ))
+    }
+    // works on a float copy of the image and returns it with the precision of the input,
+    // the alpha channel is only kept if the input has one
+    pub fn process(&self, image: &DynamicImage, context: &Context) -> Result<DynamicImage> {
+        let mut working_image = image.to_rgba32f();
+        self.apply(&mut working_image, context)?;
+        let processed = DynamicImage::ImageRgba32F(working_image);
+        let color = image.color();
+        Ok(
+            match (
+                color.bytes_per_pixel() / color.channel_count(),
+                color.has_alpha(),
+            ) {
+                (1, false) => DynamicImage::ImageRgb8(processed.to_rgb8()),
+                (1, true) => DynamicImage::ImageRgba8(processed.to_rgba8()),
+                (2, false) => DynamicImage::ImageRgb16(processed.to_rgb16()),
+                (2, true) => DynamicImage::ImageRgba16(processed.to_rgba16()),
+                (_, false) => DynamicImage::ImageRgb32F(processed.to_rgb32f()),
+                (_, true) => processed,
+            },
+        )
+    }
+    pub fn apply(&self, image: &mut Rgba32FImage, context: &Context) -> Result<()> {
+        for (index, layer) in self.layers.iter().enumerate() {
+            // every layer gets its own seed, otherwise effects of the same kind would share
+            // their noise
+            let context = Context::new(
+                context
+                    .seed
+                    .wrapping_add((index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)),
+            );
+            let overlay = layer.effect.apply(image, &context)?;
+            match &layer.mask {
+                Some(mask) => {
+                    let (width, height) = image.dimensions();
+                    palette_blend_masked(
+                        image,
+                        &overlay,
+                        &*mask.render(width, height)?,
+                        layer.opacity,
+                        self.affect_transparent,
+                        layer.blend_mode,
+                    )?;
+                }
+                None => palette_blend(
+                    image,
+                    &overlay,
+                    layer.opacity,
+                    self.affect_transparent,
+                    layer.blend_mode,
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// the look of the retrofilter app: film stock, lut, tone curve, color grade, bleach bypass,
+// vignette and film grain in this order, each with its opacity and blend mode
+pub fn retro_pipeline(
+    film_emulation: Option<(FilmEmulation, f32, BlendMode)>,
+    lut: Option<(ColorLut, f32, BlendMode)>,
+    tone_curve: Option<(ToneCurve, f32, BlendMode)>,
+    color_grade: Option<(ColorGrade, f32, BlendMode)>,
+    bleach_bypass: Option<(BleachBypass, f32, BlendMode)>,
+    vignette: Option<(Vignette, f32, BlendMode)>,
+    film_grain: Option<(FilmGrain, f32, BlendMode)>,
+) -> Pipeline {
+    let mut pipeline = Pipeline::new();
+    // the film stock renders the whole image itself
+    if let Some((effect, opacity, blend_mode)) = film_emulation {
+        pipeline.push(effect, opacity, blend_mode);
+    }
+    if let Some((effect, opacity, blend_mode)) = lut {
+        pipeline.push(effect, opacity, blend_mode);
+    }
+    if let Some((effect, opacity, blend_mode)) = tone_curve {
+        pipeline.push(effect, opacity, blend_mode);
+    }
+    if let Some((effect, opacity, blend_mode)) = color_grade {
+        pipeline.push(effect, opacity, blend_mode);
+    }
+    if let Some((effect, opacity, blend_mode)) = bleach_bypass {
+        pipeline.push(effect, opacity, blend_mode);
+    }
+    if let Some((effect, opacity, blend_mode)) = vignette {
+        pipeline.push(effect, opacity, blend_mode);
+    }
+    // the grain effect renders the grained image itself
+    if let Some((effect, opacity, blend_mode)) = film_grain {
+        pipeline.push(effect, opacity, blend_mode);
+    }
+    pipeline
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mask;
+    use image::Luma;
+
+    struct Fill([f32; 4]);
+    impl Effect for Fill {
+        fn apply(&self, image: &Rgba32FImage, _context: &Context) -> Result<Rgba32FImage> {
+            let (width, height) = image.dimensions();
+            Ok(Rgba32FImage::from_pixel(width, height, Rgba(self.0)))
+        }
+    }
+
+    fn noisy_pipeline() -> Pipeline {
+        retro_pipeline(
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some((
+                Vignette {
+                    radius: 0.5,
+                    shape: VignetteShape::Ellipse,
+                    center: (0.5, 0.5),
+                    curve: VignetteCurve::Smoothstep,
+                    noise: true,
+                },
+                0.7,
+                BlendMode::Multiply,
+            )),
+            Some((
+                FilmGrain {
+                    intensity: 0.5,
+                    size: 0.05,
+                    roughness: 0.5,
+                    color: GrainColor::Chroma {
+                        amplitude: GrainColor::DEFAULT_AMPLITUDE,
+                        saturation: 0.5,
+                    },
+                },
+                0.5,
+                BlendMode::Normal,
+            )),
+        )
+    }
+
+    fn test_image() -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(24, 16, |x, y| {
+            image::Rgb([x as u8 * 10, y as u8 * 15, 128])
+        }))
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_image() {
+        let pipeline = noisy_pipeline();
+        let image = test_image();
+        let first = pipeline.process(&image, &Context::new(42)).unwrap();
+        let second = pipeline.process(&image, &Context::new(42)).unwrap();
+        assert_eq!(first, second);
+        let other = pipeline.process(&image, &Context::new(43)).unwrap();
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn keeps_the_precision_of_the_input() {
+        let pipeline = noisy_pipeline();
+        let image = test_image();
+        for image in [
+            image.clone(),
+            DynamicImage::ImageRgba8(image.to_rgba8()),
+            DynamicImage::ImageRgb16(image.to_rgb16()),
+            DynamicImage::ImageRgba32F(image.to_rgba32f()),
+        ] {
+            let processed = pipeline.process(&image, &Context::default()).unwrap();
+            assert_eq!(processed.color(), image.color());
+        }
+    }
+
+    #[test]
+    fn image_masks_limit_the_layer() {
+        let mask = Arc::new(Mask::from_fn(2, 1, |x, _| Luma([x as f32])));
+        let pipeline =
+            Pipeline::new().with_masked(Fill([1.0; 4]), 1.0, BlendMode::Normal, mask.clone());
+        assert!(pipeline.color_only().is_empty());
+
+        let mut image = Rgba32FImage::from_pixel(2, 1, Rgba([0.0, 0.0, 0.0, 1.0]));
+        pipeline.apply(&mut image, &Context::default()).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [0.0, 0.0, 0.0, 1.0]);
+        assert!(image
+            .get_pixel(1, 0)
+            .0
+            .iter()
+            .all(|c| (c - 1.0).abs() < 1e-5));
+
+        // the mask is scaled to a larger image
+        let mut image = Rgba32FImage::from_pixel(8, 4, Rgba([0.0, 0.0, 0.0, 1.0]));
+        pipeline.apply(&mut image, &Context::default()).unwrap();
+        assert_eq!(image.get_pixel(0, 0)[0], 0.0);
+        assert!((image.get_pixel(7, 3)[0] - 1.0).abs() < 1e-5);
+    }
+}
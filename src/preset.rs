@@ -0,0 +1,585 @@
+use crate::{
+    retro_pipeline, BleachBypass, BlendMode, ColorGrade, ColorLut, Error, FilmEmulation, FilmGrain,
+    FilmStock, GrainColor, Lut, LutInterpolation, Pipeline, Result, Tint, ToneCurve, Vignette,
+    VignetteCurve, VignetteShape,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+// version of the preset format written by this crate, newer presets are rejected and older
+// ones are migrated when they are loaded
+// version 2 made the vignette radius and the bleach bypass blur relative to the image diagonal
+// version 3 replaced the grain strength from 0 to 100 with an intensity from 0 to 1
+pub const PRESET_VERSION: u32 = 3;
+
+// version 1 sizes were pixels of a 400 px preview, the diagonal of a 3:2 image at that size
+// converts them to the relative sizes
+const LEGACY_PREVIEW_DIAGONAL: f64 = 480.74;
+
+// a named look which can be stored as toml, e.g.
+//
+// name = "house look"
+// version = 3
+//
+// [film_stock]
+// stock = "portra"
+// opacity = 1.0
+// blend_mode = "soft_light"
+//
+// [lut]
+// path = "luts/teal-orange.cube"
+// opacity = 0.8
+// interpolation = "tetrahedral"
+//
+// [tone_curve]
+// opacity = 1.0
+// master = [[0.0, 0.08], [0.5, 0.5], [1.0, 0.92]]
+// blue = [[0.0, 0.05], [1.0, 1.0]]
+//
+// [color_grade]
+// opacity = 1.0
+// balance = 0.0
+// shadows = { hue = 190.0, saturation = 0.3 }
+// highlights = { hue = 35.0, saturation = 0.2 }
+//
+// [vignette]
+// radius = 0.5
+// opacity = 0.7
+// center = [0.5, 0.4]
+// curve = "smoothstep"
+// shape = { type = "rounded_rect", roundness = 0.5 }
+//
+// [grain]
+// intensity = 0.5
+// opacity = 0.2
+// size = 0.001
+// roughness = 0.5
+// color = { type = "chroma", amplitude = [0.8, 0.9, 1.0], saturation = 0.5 }
+//
+// [bleach_bypass]
+// blur = 0.0
+// opacity = 0.4
+// contrast = 0.3
+// silver_retention = 1.0
+// shadow_rolloff = 0.1
+// highlight_rolloff = 0.2
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub version: u32,
+    // seed for the randomness of the effects, stored to reproduce an image exactly
+    #[serde(default)]
+    pub seed: Option<u64>,
+    // whether the effects are also applied to transparent areas of an image
+    #[serde(default)]
+    pub affect_transparent: bool,
+    // directory of the file the preset was loaded from, a relative lut path is resolved against
+    // it when the lut is opened, it is not saved
+    #[serde(skip)]
+    pub directory: Option<PathBuf>,
+    pub film_stock: Option<FilmStockSettings>,
+    pub lut: Option<LutSettings>,
+    pub tone_curve: Option<ToneCurveSettings>,
+    pub color_grade: Option<ColorGradeSettings>,
+    pub vignette: Option<VignetteSettings>,
+    pub grain: Option<GrainSettings>,
+    pub bleach_bypass: Option<BleachBypassSettings>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FilmStockSettings {
+    pub stock: FilmStock,
+    pub opacity: f32,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+}
+
+// a .cube file or a haldclut image, a relative path is relative to the preset file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LutSettings {
+    pub path: PathBuf,
+    pub opacity: f32,
+    #[serde(default)]
+    pub interpolation: LutInterpolation,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+}
+
+// control points from 0 to 1, a missing curve keeps its channel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToneCurveSettings {
+    pub opacity: f32,
+    #[serde(default = "default_curve")]
+    pub master: Vec<(f32, f32)>,
+    #[serde(default = "default_curve")]
+    pub red: Vec<(f32, f32)>,
+    #[serde(default = "default_curve")]
+    pub green: Vec<(f32, f32)>,
+    #[serde(default = "default_curve")]
+    pub blue: Vec<(f32, f32)>,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+}
+
+// a missing tint leaves its tonal range unchanged
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorGradeSettings {
+    pub opacity: f32,
+    #[serde(default)]
+    pub balance: f32,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+    // tables have to come after the values in toml
+    #[serde(default)]
+    pub shadows: Tint,
+    #[serde(default)]
+    pub midtones: Tint,
+    #[serde(default)]
+    pub highlights: Tint,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VignetteSettings {
+    // relative to the image diagonal
+    pub radius: f64,
+    pub opacity: f32,
+    // focal point relative to the image size
+    #[serde(default = "default_center")]
+    pub center: (f32, f32),
+    #[serde(default)]
+    pub curve: VignetteCurve,
+    #[serde(default = "default_noise")]
+    pub noise: bool,
+    #[serde(default = "default_vignette_blend_mode")]
+    pub blend_mode: BlendMode,
+    // tables have to come after the values in toml
+    #[serde(default)]
+    pub shape: VignetteShape,
+}
+impl VignetteSettings {
+    pub const DEFAULT_BLEND_MODE: BlendMode = BlendMode::Multiply;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GrainSettings {
+    pub intensity: f32,
+    pub opacity: f32,
+    // relative to the image height
+    #[serde(default = "default_grain_size")]
+    pub size: f32,
+    #[serde(default = "default_grain_roughness")]
+    pub roughness: f32,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+    // tables have to come after the values in toml
+    #[serde(default)]
+    pub color: GrainColor,
+}
+impl GrainSettings {
+    pub const DEFAULT_SIZE: f32 = 0.001;
+    pub const DEFAULT_ROUGHNESS: f32 = 0.5;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BleachBypassSettings {
+    // relative to the image diagonal
+    pub blur: f32,
+    pub opacity: f32,
+    #[serde(default = "default_bleach_bypass_contrast")]
+    pub contrast: f32,
+    #[serde(default = "default_silver_retention")]
+    pub silver_retention: f32,
+    #[serde(default)]
+    pub shadow_rolloff: f32,
+    #[serde(default)]
+    pub highlight_rolloff: f32,
+    #[serde(default = "default_bleach_bypass_blend_mode")]
+    pub blend_mode: BlendMode,
+}
+impl BleachBypassSettings {
+    pub const DEFAULT_CONTRAST: f32 = 0.3;
+    pub const DEFAULT_SILVER_RETENTION: f32 = 1.0;
+    // the overlay of earlier versions picked multiply or screen by the bleach bypass layer,
+    // which is hard light, so presets without a blend mode keep their look
+    pub const DEFAULT_BLEND_MODE: BlendMode = BlendMode::HardLight;
+}
+
+// an empty parent is the working directory
+fn canonical_directory(directory: &Path) -> Option<PathBuf> {
+    let directory = if directory.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        directory
+    };
+    fs::canonicalize(directory).ok()
+}
+
+fn default_center() -> (f32, f32) {
+    (0.5, 0.5)
+}
+
+fn default_curve() -> Vec<(f32, f32)> {
+    ToneCurve::IDENTITY.to_vec()
+}
+
+fn default_grain_size() -> f32 {
+    GrainSettings::DEFAULT_SIZE
+}
+
+fn default_grain_roughness() -> f32 {
+    GrainSettings::DEFAULT_ROUGHNESS
+}
+
+fn default_noise() -> bool {
+    true
+}
+
+fn default_vignette_blend_mode() -> BlendMode {
+    VignetteSettings::DEFAULT_BLEND_MODE
+}
+
+fn default_bleach_bypass_contrast() -> f32 {
+    BleachBypassSettings::DEFAULT_CONTRAST
+}
+
+fn default_silver_retention() -> f32 {
+    BleachBypassSettings::DEFAULT_SILVER_RETENTION
+}
+
+fn default_bleach_bypass_blend_mode() -> BlendMode {
+    BleachBypassSettings::DEFAULT_BLEND_MODE
+}
+
+impl Preset {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            version: PRESET_VERSION,
+            seed: None,
+            affect_transparent: false,
+            directory: None,
+            film_stock: None,
+            lut: None,
+            tone_curve: None,
+            color_grade: None,
+            vignette: None,
+            grain: None,
+            bleach_bypass: None,
+        }
+    }
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        let mut value: toml::Value =
+            toml::from_str(toml).map_err(|err| Error::Preset(err.to_string()))?;
+        let version = value.get("version").and_then(toml::Value::as_integer);
+        if let Some(version) = version.filter(|version| *version > PRESET_VERSION as i64) {
+            return Err(Error::Preset(format!(
+                "version {} is not supported, the newest supported version is {}",
+                version, PRESET_VERSION
+            )));
+        }
+        // fields which changed their type are migrated before the preset is deserialized
+        if version.is_some_and(|version| version < 3) {
+            let grain = value.get_mut("grain").and_then(toml::Value::as_table_mut);
+            if let Some(grain) = grain {
+                if let Some(strength) = grain.remove("strength") {
+                    let strength = match strength {
+                        toml::Value::Integer(strength) => strength as f64,
+                        toml::Value::Float(strength) => strength,
+                        _ => {
+                            return Err(Error::Preset(format!(
+                                "grain strength {} is not a number",
+                                strength
+                            )))
+                        }
+                    };
+                    grain.insert(
+                        "intensity".to_string(),
+                        toml::Value::Float(strength / 100.0),
+                    );
+                }
+            }
+        }
+        let mut preset: Preset = value
+            .try_into()
+            .map_err(|err: toml::de::Error| Error::Preset(err.to_string()))?;
+        if preset.version < 2 {
+            if let Some(vignette) = &mut preset.vignette {
+                vignette.radius /= LEGACY_PREVIEW_DIAGONAL;
+            }
+            if let Some(bleach_bypass) = &mut preset.bleach_bypass {
+                bleach_bypass.blur /= LEGACY_PREVIEW_DIAGONAL as f32;
+            }
+        }
+        preset.version = PRESET_VERSION;
+        Ok(preset)
+    }
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).map_err(|err| Error::Preset(err.to_string()))
+    }
+    // the paths in the preset are kept as they are written in the file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut preset = Self::from_toml(&fs::read_to_string(path)?)?;
+        preset.directory = path.parent().map(Path::to_path_buf);
+        Ok(preset)
+    }
+    // a relative lut path is kept if the preset is saved into the directory it was loaded from,
+    // elsewhere it would point to another file and is saved as an absolute path
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut preset = self.clone();
+        if let (Some(from), Some(lut)) = (&self.directory, &mut preset.lut) {
+            let to = path.parent().unwrap_or(Path::new(""));
+            if lut.path.is_relative() && canonical_directory(from) != canonical_directory(to) {
+                lut.path = std::path::absolute(from.join(&lut.path))?;
+            }
+        }
+        fs::write(path, preset.to_toml()?)?;
+        Ok(())
+    }
+    // the path the lut is opened from
+    pub fn lut_path(&self) -> Option<PathBuf> {
+        let lut = self.lut.as_ref()?;
+        Some(match &self.directory {
+            Some(directory) => directory.join(&lut.path),
+            None => lut.path.clone(),
+        })
+    }
+    // the lut is loaded from its file, which fails if it can not be read
+    pub fn pipeline(&self) -> Result<Pipeline> {
+        let lut = match self.lut_path() {
+            Some(path) => Some(Arc::new(Lut::load(path)?)),
+            None => None,
+        };
+        Ok(self.pipeline_with_lut(lut))
+    }
+    // uses an already loaded table for the lut settings instead of reading the file, so a
+    // pipeline can be rebuilt often, without a table the lut is left out
+    pub fn pipeline_with_lut(&self, lut: Option<Arc<Lut>>) -> Pipeline {
+        let lut = match (&self.lut, lut) {
+            (Some(settings), Some(lut)) => Some((
+                ColorLut {
+                    lut,
+                    interpolation: settings.interpolation,
+                },
+                settings.opacity,
+                settings.blend_mode,
+            )),
+            _ => None,
+        };
+        retro_pipeline(
+            self.film_stock.map(|settings| {
+                (
+                    FilmEmulation {
+                        stock: settings.stock,
+                    },
+                    settings.opacity,
+                    settings.blend_mode,
+                )
+            }),
+            lut,
+            self.tone_curve.as_ref().map(|settings| {
+                (
+                    ToneCurve {
+                        master: settings.master.clone(),
+                        red: settings.red.clone(),
+                        green: settings.green.clone(),
+                        blue: settings.blue.clone(),
+                    },
+                    settings.opacity,
+                    settings.blend_mode,
+                )
+            }),
+            self.color_grade.map(|settings| {
+                (
+                    ColorGrade {
+                        shadows: settings.shadows,
+                        midtones: settings.midtones,
+                        highlights: settings.highlights,
+                        balance: settings.balance,
+                    },
+                    settings.opacity,
+                    settings.blend_mode,
+                )
+            }),
+            self.bleach_bypass.map(|settings| {
+                (
+                    BleachBypass {
+                        blur: settings.blur,
+                        contrast: settings.contrast,
+                        silver_retention: settings.silver_retention,
+                        shadow_rolloff: settings.shadow_rolloff,
+                        highlight_rolloff: settings.highlight_rolloff,
+                    },
+                    settings.opacity,
+                    settings.blend_mode,
+                )
+            }),
+            self.vignette.map(|settings| {
+                (
+                    Vignette {
+                        radius: settings.radius,
+                        shape: settings.shape,
+                        center: settings.center,
+                        curve: settings.curve,
+                        noise: settings.noise,
+                    },
+                    settings.opacity,
+                    settings.blend_mode,
+                )
+            }),
+            self.grain.map(|settings| {
+                (
+                    FilmGrain {
+                        intensity: settings.intensity,
+                        size: settings.size,
+                        roughness: settings.roughness,
+                        color: settings.color,
+                    },
+                    settings.opacity,
+                    settings.blend_mode,
+                )
+            }),
+        )
+        .affect_transparent(self.affect_transparent)
+    }
+}
+impl Default for Preset {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERSION_1: &str = r#"
+name = "old look"
+version = 1
+
+[vignette]
+radius = 240.37
+opacity = 0.7
+
+[grain]
+strength = 50
+opacity = 0.2
+
+[bleach_bypass]
+blur = 4.8074
+opacity = 0.4
+"#;
+
+    #[test]
+    fn migrates_version_1() {
+        let preset = Preset::from_toml(VERSION_1).unwrap();
+        assert_eq!(preset.version, PRESET_VERSION);
+        let vignette = preset.vignette.unwrap();
+        assert!((vignette.radius - 240.37 / LEGACY_PREVIEW_DIAGONAL).abs() < 1e-9);
+        let bleach_bypass = preset.bleach_bypass.unwrap();
+        assert!((bleach_bypass.blur - 0.01).abs() < 1e-6);
+        assert_eq!(bleach_bypass.blend_mode, BlendMode::HardLight);
+        let grain = preset.grain.unwrap();
+        assert_eq!(grain.intensity, 0.5);
+        assert_eq!(grain.opacity, 0.2);
+    }
+
+    #[test]
+    fn migrates_a_float_grain_strength() {
+        let toml = VERSION_1.replace("strength = 50", "strength = 37.5");
+        let grain = Preset::from_toml(&toml).unwrap().grain.unwrap();
+        assert_eq!(grain.intensity, 0.375);
+
+        let toml = VERSION_1.replace("strength = 50", "strength = \"strong\"");
+        assert!(matches!(Preset::from_toml(&toml), Err(Error::Preset(_))));
+    }
+
+    #[test]
+    fn keeps_the_sizes_of_version_2() {
+        let toml = VERSION_1
+            .replace("version = 1", "version = 2")
+            .replace("radius = 240.37", "radius = 0.5");
+        let preset = Preset::from_toml(&toml).unwrap();
+        assert_eq!(preset.vignette.unwrap().radius, 0.5);
+        assert_eq!(preset.grain.unwrap().intensity, 0.5);
+        // the bleach bypass keeps the look of the old overlay
+        assert_eq!(
+            preset.bleach_bypass.unwrap().blend_mode,
+            BlendMode::HardLight
+        );
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let toml = format!("name = \"new look\"\nversion = {}\n", PRESET_VERSION + 1);
+        let err = Preset::from_toml(&toml).unwrap_err();
+        assert!(matches!(err, Error::Preset(_)));
+        assert!(err.to_string().contains("not supported"));
+    }
+
+    #[test]
+    fn keeps_relative_lut_paths() {
+        let directory =
+            std::env::temp_dir().join(format!("retrofilter-preset-{}", std::process::id()));
+        let elsewhere = directory.join("elsewhere");
+        fs::create_dir_all(&elsewhere).unwrap();
+        let path = directory.join("look.toml");
+        let mut preset = Preset::new("look");
+        preset.lut = Some(LutSettings {
+            path: PathBuf::from("luts/teal.cube"),
+            opacity: 0.8,
+            interpolation: LutInterpolation::Tetrahedral,
+            blend_mode: BlendMode::Normal,
+        });
+        preset.save(&path).unwrap();
+
+        let loaded = Preset::load(&path).unwrap();
+        assert_eq!(
+            loaded.lut.as_ref().unwrap().path,
+            Path::new("luts/teal.cube")
+        );
+        assert_eq!(loaded.lut_path().unwrap(), directory.join("luts/teal.cube"));
+        // saved next to itself the path stays relative
+        loaded.save(&path).unwrap();
+        let reloaded = Preset::load(&path).unwrap();
+        assert_eq!(reloaded.lut, preset.lut);
+        // saved elsewhere it still points to the same file
+        let moved_path = elsewhere.join("look.toml");
+        reloaded.save(&moved_path).unwrap();
+        let moved = Preset::load(&moved_path).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        let moved_lut = moved.lut_path().unwrap();
+        assert!(moved_lut.is_absolute());
+        assert_eq!(
+            moved_lut,
+            std::path::absolute(directory.join("luts/teal.cube")).unwrap()
+        );
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut preset = Preset::from_toml(VERSION_1).unwrap();
+        preset.seed = Some(7);
+        preset.color_grade = Some(ColorGradeSettings {
+            opacity: 1.0,
+            balance: 0.2,
+            blend_mode: BlendMode::Normal,
+            shadows: Tint {
+                hue: 190.0,
+                saturation: 0.3,
+            },
+            midtones: Tint::default(),
+            highlights: Tint {
+                hue: 35.0,
+                saturation: 0.2,
+            },
+        });
+        let toml = preset.to_toml().unwrap();
+        assert_eq!(Preset::from_toml(&toml).unwrap(), preset);
+    }
+}
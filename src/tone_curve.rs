@@ -0,0 +1,205 @@
+use crate::{ColorPixel, Error, Result, ToneCurve};
+use image::ImageBuffer;
+use rayon::prelude::*;
+
+// entries of the tables the curves are sampled into before they are applied
+const TABLE_SIZE: usize = 4096;
+
+// a monotone cubic spline through control points from 0 to 1, it does not overshoot between
+// the points, so a curve stays within the range of its points
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spline {
+    points: Vec<(f32, f32)>,
+    slopes: Vec<f32>,
+}
+impl Spline {
+    // the points are sorted, at least two with different x are needed
+    pub fn new(points: &[(f32, f32)]) -> Result<Self> {
+        if points.len() < 2 {
+            return Err(Error::invalid_parameter(
+                "points",
+                format!("a curve needs at least 2 points but has {}", points.len()),
+            ));
+        }
+        if let Some(point) = points
+            .iter()
+            .find(|(x, y)| !(0.0..=1.0).contains(x) || !(0.0..=1.0).contains(y))
+        {
+            return Err(Error::invalid_parameter(
+                "points",
+                format!("{:?} is not between 0 and 1", point),
+            ));
+        }
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some(pair) = points.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(Error::invalid_parameter(
+                "points",
+                format!("{:?} and {:?} have the same x", pair[0], pair[1]),
+            ));
+        }
+
+        // fritsch-carlson: the slopes are averaged between the points, flattened at local
+        // extrema and limited so each segment stays monotone
+        let secants: Vec<f32> = points
+            .windows(2)
+            .map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0))
+            .collect();
+        let mut slopes = Vec::with_capacity(points.len());
+        slopes.push(secants[0]);
+        for pair in secants.windows(2) {
+            slopes.push(if pair[0] * pair[1] <= 0.0 {
+                0.0
+            } else {
+                (pair[0] + pair[1]) / 2.0
+            });
+        }
+        slopes.push(secants[secants.len() - 1]);
+        for (index, secant) in secants.iter().enumerate() {
+            if *secant == 0.0 {
+                slopes[index] = 0.0;
+                slopes[index + 1] = 0.0;
+                continue;
+            }
+            let (a, b) = (slopes[index] / secant, slopes[index + 1] / secant);
+            let length = a.hypot(b);
+            if length > 3.0 {
+                slopes[index] = 3.0 * a / length * secant;
+                slopes[index + 1] = 3.0 * b / length * secant;
+            }
+        }
+        Ok(Self { points, slopes })
+    }
+
+    // values outside of the points continue the first and the last point
+    pub fn value(&self, x: f32) -> f32 {
+        let (first, last) = (self.points[0], self.points[self.points.len() - 1]);
+        if x <= first.0 {
+            return first.1;
+        }
+        if x >= last.0 {
+            return last.1;
+        }
+        let index = self.points.partition_point(|point| point.0 <= x) - 1;
+        let ((x0, y0), (x1, y1)) = (self.points[index], self.points[index + 1]);
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        let value = (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * h * self.slopes[index]
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * h * self.slopes[index + 1];
+        value.clamp(0.0, 1.0)
+    }
+
+    fn table(&self) -> Vec<f32> {
+        (0..TABLE_SIZE)
+            .map(|index| self.value(index as f32 / (TABLE_SIZE - 1) as f32))
+            .collect()
+    }
+}
+
+// maps every channel through its own curve and then through the master curve, the curves work
+// on the gamma encoded values like the curves of an image editor
+pub fn tone_curve<P: ColorPixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    tone_curve: &ToneCurve,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    let master = Spline::new(&tone_curve.master)?.table();
+    let tables = [
+        Spline::new(&tone_curve.red)?.table(),
+        Spline::new(&tone_curve.green)?.table(),
+        Spline::new(&tone_curve.blue)?.table(),
+    ];
+
+    let mut output = image.clone();
+    output.par_pixels_mut().for_each(|px| {
+        let [red, green, blue, alpha] = px.to_rgba_f32();
+        let rgb = [red, green, blue];
+        let [red, green, blue] =
+            std::array::from_fn(|c| lookup(&master, lookup(&tables[c], rgb[c])));
+        *px = P::from_rgba_f32([red, green, blue, alpha]);
+    });
+    Ok(output)
+}
+
+// linear interpolation between the entries of a sampled curve
+fn lookup(table: &[f32], value: f32) -> f32 {
+    let position = value.clamp(0.0, 1.0) * (TABLE_SIZE - 1) as f32;
+    let index = (position as usize).min(TABLE_SIZE - 2);
+    let fraction = position - index as f32;
+    table[index] + (table[index + 1] - table[index]) * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(spline: &Spline) -> Vec<f32> {
+        (0..=1000)
+            .map(|x| spline.value(x as f32 / 1000.0))
+            .collect()
+    }
+
+    #[test]
+    fn passes_through_the_points() {
+        let points = [(0.0, 0.1), (0.3, 0.2), (0.6, 0.8), (1.0, 0.9)];
+        let spline = Spline::new(&points).unwrap();
+        for (x, y) in points {
+            assert!((spline.value(x) - y).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn is_monotone_between_monotone_points() {
+        // steep and flat segments next to each other make an unlimited spline overshoot
+        for points in [
+            &[(0.0, 0.0), (0.1, 0.5), (0.2, 0.52), (1.0, 1.0)][..],
+            &[(0.0, 0.0), (0.45, 0.1), (0.55, 0.9), (1.0, 1.0)],
+            &[(0.0, 1.0), (0.5, 0.95), (0.6, 0.1), (1.0, 0.0)],
+        ] {
+            let values = samples(&Spline::new(points).unwrap());
+            let rising = points[0].1 < points[points.len() - 1].1;
+            for pair in values.windows(2) {
+                if rising {
+                    assert!(pair[1] >= pair[0], "{:?} falls at {:?}", points, pair);
+                } else {
+                    assert!(pair[1] <= pair[0], "{:?} rises at {:?}", points, pair);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stays_within_its_points() {
+        let points = [(0.0, 0.2), (0.4, 0.8), (0.5, 0.8), (0.7, 0.3), (1.0, 0.6)];
+        let spline = Spline::new(&points).unwrap();
+        for value in samples(&spline) {
+            assert!(value > 0.2 - 1e-6 && value < 0.8 + 1e-6, "{}", value);
+        }
+        // the flat segment stays flat
+        assert!((spline.value(0.45) - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn continues_the_end_points() {
+        let spline = Spline::new(&[(0.2, 0.1), (0.8, 0.7)]).unwrap();
+        assert_eq!(spline.value(0.0), 0.1);
+        assert_eq!(spline.value(1.0), 0.7);
+        assert!((spline.value(0.5) - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rejects_invalid_points() {
+        for points in [
+            &[(0.5, 0.5)][..],
+            &[(0.0, 0.0), (1.0, 1.5)],
+            &[(0.0, 0.0), (0.5, 0.2), (0.5, 0.4), (1.0, 1.0)],
+        ] {
+            assert!(matches!(
+                Spline::new(points),
+                Err(Error::InvalidParameter { name: "points", .. })
+            ));
+        }
+    }
+}
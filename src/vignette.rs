@@ -1,64 +1,337 @@
-use image::{ImageBuffer, Rgb};
-use imageproc::drawing::draw_filled_circle_mut;
+use crate::{Channel, ColorPixel, Error, Result, Vignette};
+use image::ImageBuffer;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
-pub fn create_vignette(
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VignetteShape {
+    #[default]
+    Circle,
+    // ellipse with the aspect ratio of the image
+    Ellipse,
+    // rectangle with the aspect ratio of the image, a roundness of 0 gives sharp corners and 1
+    // an ellipse
+    RoundedRect {
+        roundness: f32,
+    },
+}
+impl VignetteShape {
+    // half axes relative to the radius and the exponent of the superellipse, the axes are scaled
+    // so every shape reaches the corners of the frame at the same radius as the circle
+    fn geometry(&self, width: u32, height: u32) -> (f32, f32, f32) {
+        let short_side = width.min(height).max(1) as f32;
+        let (aspect_x, aspect_y) = (width as f32 / short_side, height as f32 / short_side);
+        let (aspect_x, aspect_y, exponent) = match self {
+            VignetteShape::Circle => return (1.0, 1.0, 2.0),
+            VignetteShape::Ellipse => (aspect_x, aspect_y, 2.0),
+            VignetteShape::RoundedRect { roundness } => {
+                (aspect_x, aspect_y, 2.0 + (1.0 - roundness) * 18.0)
+            }
+        };
+        let (half_width, half_height) = (width as f32 / 2.0, height as f32 / 2.0);
+        let half_diagonal = half_width.hypot(half_height);
+        let corner = superellipse(half_width / aspect_x, half_height / aspect_y, exponent);
+        if half_diagonal == 0.0 {
+            return (aspect_x, aspect_y, exponent);
+        }
+        let scale = corner / half_diagonal;
+        (aspect_x * scale, aspect_y * scale, exponent)
+    }
+}
+
+// radius of the superellipse through the point, an exponent of 2 gives a circle
+fn superellipse(dx: f32, dy: f32, exponent: f32) -> f32 {
+    if exponent == 2.0 {
+        return dx.hypot(dy);
+    }
+    // the powers are taken relative to the larger distance, pixel distances to the power of 20
+    // would overflow
+    let max = dx.abs().max(dy.abs());
+    if max == 0.0 {
+        return 0.0;
+    }
+    max * ((dx / max).abs().powf(exponent) + (dy / max).abs().powf(exponent)).powf(1.0 / exponent)
+}
+
+// falloff from the edge of the vignette to its clear center
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VignetteCurve {
+    #[default]
+    Linear,
+    Smoothstep,
+    // natural light falloff of a lens
+    Cos4,
+}
+impl VignetteCurve {
+    // t goes from 0 at the edge to 1 at the clear center
+    fn apply(&self, t: f32) -> f32 {
+        match self {
+            VignetteCurve::Linear => t,
+            VignetteCurve::Smoothstep => t * t * (3.0 - 2.0 * t),
+            VignetteCurve::Cos4 => ((1.0 - t) * std::f32::consts::FRAC_PI_2).cos().powi(4),
+        }
+    }
+}
+
+// the radius of the vignette is relative to the image diagonal, at 0.5 every shape around a
+// centered focal point reaches the corners of the frame, every pixel is computed on its own from
+// the distance to the focal point
+pub fn create_vignette<P: ColorPixel>(
     width: u32,
     height: u32,
-    radius: u32,
-    noise: bool,
-) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
-    let (center_x, center_y) = ((width / 2) as i32, (height / 2) as i32);
-    let mut buffer = ImageBuffer::from_fn(width, height, |_x, _y| Rgb([0u8, 0u8, 0u8]));
-
-    // partial radius should be gradient
-    let inner_radius = (radius as f32 * 0.33).round() as u32;
-    let outer_radius = radius - inner_radius;
-
-    // draw clear center
-    draw_filled_circle_mut(
-        &mut buffer,
-        (center_x, center_y),
-        inner_radius as i32,
-        Rgb([255u8, 255u8, 255u8]),
-    );
-
-    // draw gradient
-    let alpha_step = 255.0 / outer_radius as f32;
-    (1..=outer_radius).into_iter().for_each(|i| {
-        let alpha = ((alpha_step * i as f32).round() as u8).clamp(0u8, 255u8);
-        draw_filled_circle_mut(
-            &mut buffer,
-            (center_x, center_y),
-            (radius - i) as i32,
-            Rgb([alpha, alpha, alpha]),
-        );
-    });
+    vignette: &Vignette,
+    seed: u64,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    let Vignette {
+        radius,
+        shape,
+        center,
+        curve,
+        noise,
+    } = *vignette;
+    if !(0.0..).contains(&radius) {
+        return Err(Error::invalid_parameter(
+            "radius",
+            format!("{} is not a positive number", radius),
+        ));
+    }
+    if !(0.0..=1.0).contains(&center.0) || !(0.0..=1.0).contains(&center.1) {
+        return Err(Error::invalid_parameter(
+            "center",
+            format!("{:?} is not between 0 and 1", center),
+        ));
+    }
+    if let VignetteShape::RoundedRect { roundness } = shape {
+        if !(0.0..=1.0).contains(&roundness) {
+            return Err(Error::invalid_parameter(
+                "roundness",
+                format!("{} is not between 0 and 1", roundness),
+            ));
+        }
+    }
+    let (scale_x, scale_y, exponent) = shape.geometry(width, height);
+    let center = (width as f32 * center.0, height as f32 * center.1);
+    let mut buffer = ImageBuffer::new(width, height);
+
+    // the inner third of the radius stays clear, the rest is the gradient
+    let radius = (radius * (width as f64).hypot(height as f64)) as f32;
+    let outer_radius = radius * 0.67;
+
+    let channels = P::CHANNEL_COUNT as usize;
+    let row_length = (width as usize * channels).max(1);
+    buffer
+        .par_chunks_mut(row_length)
+        .enumerate()
+        .for_each(|(y, pixels)| {
+            let dy = ((y as f32 + 0.5 - center.1) / scale_y).abs();
+            pixels
+                .chunks_exact_mut(channels)
+                .enumerate()
+                .for_each(|(x, pixel)| {
+                    let dx = ((x as f32 + 0.5 - center.0) / scale_x).abs();
+                    let distance = superellipse(dx, dy, exponent);
+                    let t = if outer_radius > 0.0 {
+                        ((radius - distance) / outer_radius).clamp(0.0, 1.0)
+                    } else if distance <= radius {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                    let value = curve.apply(t);
+                    let color = P::from_rgba_f32([value, value, value, 1.0]);
+                    pixel.copy_from_slice(color.channels());
+                });
+        });
 
     if noise {
-        gradient_noise(&mut buffer);
+        gradient_noise(&mut buffer, seed);
     }
 
-    buffer
+    Ok(buffer)
 }
 
-#[allow(clippy::redundant_closure)]
-fn gradient_noise(buffer: &mut ImageBuffer<Rgb<u8>, Vec<u8>>) {
-    use rand::prelude::*;
-    use rayon::prelude::*;
+fn gradient_noise<P: ColorPixel>(buffer: &mut ImageBuffer<P, Vec<P::Subpixel>>, seed: u64) {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     // optionally add noise to gradient in order to break up banding artefacts
-    buffer.par_iter_mut().for_each_init(
-        // clippy warns but it seems to be necessary to do like this according to rayon
-        || rand::thread_rng(),
-        |rng, p| {
-            if *p != 0 && *p != 255 {
-                let random: i32 = *p as i32 + rng.gen_range(-10..10);
-                if random < 0 {
-                    *p = 0
-                } else {
-                    *p = random.clamp(0, 255) as u8
-                }
+    // every row gets its own rng derived from the seed so the result does not depend on
+    // how rayon splits the work
+    let channels = P::CHANNEL_COUNT as usize;
+    let row_length = (buffer.width() as usize * channels).max(1);
+    buffer
+        .par_chunks_mut(row_length)
+        .enumerate()
+        .for_each(|(row, pixels)| {
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(row as u64));
+            pixels.chunks_exact_mut(channels).for_each(|pixel| {
+                // only the color channels get noise
+                pixel.iter_mut().take(3).for_each(|p| {
+                    let value = p.to_f32();
+                    if value > 0.0 && value < 1.0 {
+                        let random = value + rng.gen_range(-10..10) as f32 / 255.0;
+                        *p = Channel::from_f32(random.clamp(0.0, 1.0))
+                    }
+                });
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, Rgb32FImage};
+
+    fn vignette(radius: f64, shape: VignetteShape) -> Vignette {
+        Vignette {
+            radius,
+            shape,
+            center: (0.5, 0.5),
+            curve: VignetteCurve::Linear,
+            noise: false,
+        }
+    }
+
+    fn render(width: u32, height: u32, vignette: &Vignette) -> Rgb32FImage {
+        create_vignette(width, height, vignette, 0).unwrap()
+    }
+
+    #[test]
+    fn ellipse_darkens_the_edge_midpoints_equally() {
+        for shape in [
+            VignetteShape::Ellipse,
+            VignetteShape::RoundedRect { roundness: 0.5 },
+        ] {
+            let image = render(301, 201, &vignette(0.4, shape));
+            let (left, top) = (image.get_pixel(0, 100)[0], image.get_pixel(150, 0)[0]);
+            assert!(left < 0.9, "{:?} does not darken the edges", shape);
+            assert!(
+                (left - top).abs() < 0.01,
+                "{:?}: {} and {}",
+                shape,
+                left,
+                top
+            );
+        }
+    }
+
+    #[test]
+    fn every_shape_reaches_the_corners_at_half_the_diagonal() {
+        for shape in [
+            VignetteShape::Circle,
+            VignetteShape::Ellipse,
+            VignetteShape::RoundedRect { roundness: 0.0 },
+            VignetteShape::RoundedRect { roundness: 0.5 },
+        ] {
+            // the corner pixels are half a pixel inside the corners of the frame
+            let image = render(300, 200, &vignette(0.5, shape));
+            let corner = image.get_pixel(0, 0)[0];
+            assert!(corner < 0.01, "{:?}: {}", shape, corner);
+            let image = render(300, 200, &vignette(0.52, shape));
+            assert!(image.get_pixel(0, 0)[0] > 0.0, "{:?}", shape);
+        }
+    }
+
+    #[test]
+    fn circle_is_unchanged() {
+        let (width, height) = (300, 200);
+        let image = render(width, height, &vignette(0.4, VignetteShape::Circle));
+        let radius = 0.4 * (width as f32).hypot(height as f32);
+        for (x, y) in [(0, 0), (20, 100), (150, 10), (100, 60), (150, 100)] {
+            let distance = (x as f32 + 0.5 - 150.0).hypot(y as f32 + 0.5 - 100.0);
+            let expected = ((radius - distance) / (radius * 0.67)).clamp(0.0, 1.0);
+            assert!((image.get_pixel(x, y)[0] - expected).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let invalid = [
+            ("radius", vignette(-0.1, VignetteShape::Circle)),
+            (
+                "roundness",
+                vignette(0.5, VignetteShape::RoundedRect { roundness: 1.5 }),
+            ),
+            (
+                "roundness",
+                vignette(0.5, VignetteShape::RoundedRect { roundness: -0.5 }),
+            ),
+            (
+                "center",
+                Vignette {
+                    center: (1.2, 0.5),
+                    ..vignette(0.5, VignetteShape::Circle)
+                },
+            ),
+            (
+                "center",
+                Vignette {
+                    center: (0.5, -0.1),
+                    ..vignette(0.5, VignetteShape::Circle)
+                },
+            ),
+        ];
+        for (name, vignette) in invalid {
+            match create_vignette::<Rgb<f32>>(10, 10, &vignette, 0) {
+                Err(Error::InvalidParameter { name: found, .. }) => assert_eq!(found, name),
+                result => panic!("{:?} gave {:?}", vignette, result.map(|_| ())),
             }
-        },
-    );
+        }
+    }
+
+    #[test]
+    fn clear_area_follows_the_focal_point() {
+        let off_center = Vignette {
+            center: (0.25, 0.75),
+            ..vignette(0.3, VignetteShape::Ellipse)
+        };
+        let image = render(200, 100, &off_center);
+        // the clear area is centered on the focal point
+        let clear: Vec<(u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(_, _, px)| px[0] == 1.0)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(!clear.is_empty());
+        let count = clear.len() as f32;
+        let mean_x = clear.iter().map(|(x, _)| *x as f32 + 0.5).sum::<f32>() / count;
+        let mean_y = clear.iter().map(|(_, y)| *y as f32 + 0.5).sum::<f32>() / count;
+        assert!((mean_x - 50.0).abs() < 0.5 && (mean_y - 75.0).abs() < 0.5);
+        // the opposite corner is farther away and darker than the near one
+        assert!(image.get_pixel(199, 0)[0] < image.get_pixel(0, 99)[0]);
+        assert_eq!(image.get_pixel(199, 0)[0], 0.0);
+    }
+
+    // the vignette drawn as filled circles before it was computed per pixel
+    fn drawn_vignette(width: u32, height: u32, radius: u32) -> Vec<f32> {
+        let (center_x, center_y) = ((width / 2) as f32, (height / 2) as f32);
+        let inner_radius = (radius as f32 * 0.33).round() as u32;
+        let outer_radius = radius - inner_radius;
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let distance = (x as f32 - center_x).hypot(y as f32 - center_y);
+                // the smallest circle around the pixel decides its value
+                let step = (radius as f32 - distance).floor();
+                step.clamp(0.0, outer_radius as f32) / outer_radius as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_the_drawn_vignette() {
+        // a diagonal of 150 px gives a radius of 60 px
+        let image = render(120, 90, &vignette(0.4, VignetteShape::Circle));
+        let drawn = drawn_vignette(120, 90, 60);
+        let differences: Vec<f32> = image
+            .pixels()
+            .zip(&drawn)
+            .map(|(px, drawn)| (px[0] - drawn).abs())
+            .collect();
+        let max = differences.iter().copied().fold(0.0, f32::max);
+        let mean = differences.iter().sum::<f32>() / differences.len() as f32;
+        assert!(max < 0.06, "{}", max);
+        assert!(mean < 0.02, "{}", mean);
+    }
 }
@@ -0,0 +1,102 @@
+use image::{Rgb, RgbImage};
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+fn cli(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_retrofilter-cli"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// a fresh directory per test, the tests run in parallel
+fn directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("retrofilter-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[test]
+fn help_exits_with_success() {
+    let output = cli(&["--help"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Usage:"));
+}
+
+#[test]
+fn invalid_arguments_exit_with_2() {
+    for args in [
+        &[][..],
+        &["--vinyette", "0.5,0.7", "-o", "out", "a.jpg"],
+        &["--vignette", "0.5", "-o", "out", "a.jpg"],
+        &["-o", "out", "does-not-exist-*.jpg"],
+    ] {
+        let output = cli(args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("error:"));
+    }
+}
+
+#[test]
+fn failed_images_exit_with_1() {
+    let directory = directory("failed");
+    let image = directory.join("image.png");
+    RgbImage::from_pixel(8, 6, Rgb([120, 80, 40]))
+        .save(&image)
+        .unwrap();
+    let broken = directory.join("broken.png");
+    fs::write(&broken, b"not a png").unwrap();
+    let output_directory = directory.join("out");
+
+    let output = cli(&[
+        "--vignette",
+        "0.5,0.7",
+        "--seed",
+        "1",
+        "-f",
+        "png",
+        "-o",
+        output_directory.to_str().unwrap(),
+        image.to_str().unwrap(),
+        broken.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    // the other images are still processed
+    assert!(output_directory.join("image.png").is_file());
+    assert!(!output_directory.join("broken.png").exists());
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn processed_images_are_the_same_for_a_seed() {
+    let directory = directory("seed");
+    let image = directory.join("image.png");
+    RgbImage::from_fn(16, 12, |x, y| Rgb([x as u8 * 16, y as u8 * 20, 128]))
+        .save(&image)
+        .unwrap();
+    let outputs = ["first", "second"].map(|name| {
+        let output_directory = directory.join(name);
+        let output = cli(&[
+            "--filmgrain",
+            "0.5,0.5",
+            "--seed",
+            "42",
+            "-f",
+            "png",
+            "-o",
+            output_directory.to_str().unwrap(),
+            image.to_str().unwrap(),
+        ]);
+        assert_eq!(output.status.code(), Some(0));
+        image::open(output_directory.join("image.png"))
+            .unwrap()
+            .to_rgb8()
+    });
+    assert_eq!(outputs[0], outputs[1]);
+    fs::remove_dir_all(&directory).unwrap();
+}